@@ -0,0 +1,231 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use chrono::{Datelike, Duration, NaiveDate};
+use rust_decimal::Decimal;
+
+use crate::error::Error;
+
+// calendar period rates are grouped by
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Group {
+    Week,
+    Month,
+    Quarter,
+    Year,
+}
+
+impl Group {
+    pub fn from_param(group: &str) -> Result<Group, Error> {
+        match group {
+            "week" => Ok(Group::Week),
+            "month" => Ok(Group::Month),
+            "quarter" => Ok(Group::Quarter),
+            "year" => Ok(Group::Year),
+            _ => Err(Error::InvalidGroup(group.to_string())),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Group::Week => "week",
+            Group::Month => "month",
+            Group::Quarter => "quarter",
+            Group::Year => "year",
+        }
+    }
+
+    // first day of the period `date` belongs to, weeks start on monday
+    fn period_start(&self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Group::Week => date - Duration::days(date.weekday().num_days_from_monday() as i64),
+            Group::Month => NaiveDate::from_ymd(date.year(), date.month(), 1),
+            Group::Quarter => NaiveDate::from_ymd(date.year(), (date.month() - 1) / 3 * 3 + 1, 1),
+            Group::Year => NaiveDate::from_ymd(date.year(), 1, 1),
+        }
+    }
+}
+
+// how the rates of a period are reduced to a single rate
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Aggregation {
+    Last,
+    First,
+    Mean,
+    Min,
+    Max,
+}
+
+impl Aggregation {
+    pub fn from_param(agg: Option<&str>) -> Result<Aggregation, Error> {
+        match agg {
+            None | Some("last") => Ok(Aggregation::Last),
+            Some("first") => Ok(Aggregation::First),
+            Some("mean") => Ok(Aggregation::Mean),
+            Some("min") => Ok(Aggregation::Min),
+            Some("max") => Ok(Aggregation::Max),
+            Some(agg) => Err(Error::InvalidAggregation(agg.to_string())),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Aggregation::Last => "last",
+            Aggregation::First => "first",
+            Aggregation::Mean => "mean",
+            Aggregation::Min => "min",
+            Aggregation::Max => "max",
+        }
+    }
+
+    // values are in date order and never empty
+    fn apply(&self, values: &[Decimal]) -> Result<Decimal, Error> {
+        let value = match self {
+            Aggregation::Last => values.last().copied(),
+            Aggregation::First => values.first().copied(),
+            Aggregation::Min => values.iter().min().copied(),
+            Aggregation::Max => values.iter().max().copied(),
+            Aggregation::Mean => values
+                .iter()
+                .try_fold(Decimal::ZERO, |sum, value| sum.checked_add(*value))
+                .and_then(|sum| sum.checked_div(Decimal::from(values.len()))),
+        };
+        value.ok_or(Error::EmpyDataset)
+    }
+}
+
+// start of a period and the rates of each of its dates
+type Period<'a> = (NaiveDate, Vec<(NaiveDate, &'a [(String, Decimal)])>);
+
+// rates of a period, `start` and `end` are the first and last dates with rates in it
+#[derive(Debug, PartialEq)]
+pub struct Bucket {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub rates: Vec<(String, Decimal)>,
+}
+
+// group the rates of each date, ascending, into periods and aggregate each currency's rates
+pub fn aggregate(
+    rates: &BTreeMap<String, Vec<(String, Decimal)>>,
+    group: Group,
+    agg: Aggregation,
+) -> Result<Vec<Bucket>, Error> {
+    let mut periods: Vec<Period> = Vec::new();
+    for (date, currencies) in rates {
+        let date = NaiveDate::from_str(date).map_err(|err| {
+            Error::DateParse(format!("could not parse {} as NaiveDate", date), err)
+        })?;
+        let start = group.period_start(date);
+        match periods.last_mut() {
+            Some((period, dates)) if *period == start => dates.push((date, currencies)),
+            _ => periods.push((start, vec![(date, currencies)])),
+        }
+    }
+
+    periods
+        .into_iter()
+        .map(|(_, dates)| {
+            // currencies keep the order they first appear in
+            let mut values: Vec<(&str, Vec<Decimal>)> = Vec::new();
+            for (_, currencies) in &dates {
+                for (name, rate) in currencies.iter() {
+                    match values.iter_mut().find(|(n, _)| n == name) {
+                        Some((_, rates)) => rates.push(*rate),
+                        None => values.push((name, vec![*rate])),
+                    }
+                }
+            }
+
+            let rates = values
+                .into_iter()
+                .map(|(name, rates)| Ok((name.to_string(), agg.apply(&rates)?)))
+                .collect::<Result<Vec<(String, Decimal)>, Error>>()?;
+
+            Ok(Bucket {
+                // safe to unwrap as periods are only created with a date
+                start: dates.first().unwrap().0,
+                end: dates.last().unwrap().0,
+                rates,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rates(days: &[(&str, &str)]) -> BTreeMap<String, Vec<(String, Decimal)>> {
+        days.iter()
+            .map(|(day, rate)| {
+                (
+                    day.to_string(),
+                    vec![("USD".to_string(), Decimal::from_str(rate).unwrap())],
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn period_start() {
+        let date = NaiveDate::from_ymd(2019, 8, 15);
+        assert_eq!(
+            Group::Week.period_start(date),
+            NaiveDate::from_ymd(2019, 8, 12)
+        );
+        assert_eq!(
+            Group::Month.period_start(date),
+            NaiveDate::from_ymd(2019, 8, 1)
+        );
+        assert_eq!(
+            Group::Quarter.period_start(date),
+            NaiveDate::from_ymd(2019, 7, 1)
+        );
+        assert_eq!(
+            Group::Year.period_start(date),
+            NaiveDate::from_ymd(2019, 1, 1)
+        );
+    }
+
+    #[test]
+    fn aggregate_monthly() {
+        let rates = rates(&[
+            ("2019-07-30", "1.1"),
+            ("2019-07-31", "1.2"),
+            ("2019-08-01", "1.4"),
+            ("2019-08-02", "1.3"),
+            ("2019-08-05", "1.6"),
+        ]);
+
+        let expected = |agg: Aggregation, july: &str, august: &str| {
+            let buckets = aggregate(&rates, Group::Month, agg).unwrap();
+            assert_eq!(buckets.len(), 2);
+            assert_eq!(buckets[0].start, NaiveDate::from_ymd(2019, 7, 30));
+            assert_eq!(buckets[0].end, NaiveDate::from_ymd(2019, 7, 31));
+            assert_eq!(buckets[1].start, NaiveDate::from_ymd(2019, 8, 1));
+            assert_eq!(buckets[1].end, NaiveDate::from_ymd(2019, 8, 5));
+            assert_eq!(buckets[0].rates[0].1, Decimal::from_str(july).unwrap());
+            assert_eq!(buckets[1].rates[0].1, Decimal::from_str(august).unwrap());
+        };
+        expected(Aggregation::Last, "1.2", "1.6");
+        expected(Aggregation::First, "1.1", "1.4");
+        expected(Aggregation::Mean, "1.15", "1.4333333333333333333333333333");
+        expected(Aggregation::Min, "1.1", "1.3");
+        expected(Aggregation::Max, "1.2", "1.6");
+    }
+
+    #[test]
+    fn aggregate_weekly() {
+        let rates = rates(&[
+            ("2019-08-02", "1.3"),
+            ("2019-08-05", "1.6"),
+            ("2019-08-09", "1.4"),
+        ]);
+        let buckets = aggregate(&rates, Group::Week, Aggregation::Max).unwrap();
+        assert_eq!(buckets.len(), 2);
+        assert_eq!(buckets[1].start, NaiveDate::from_ymd(2019, 8, 5));
+        assert_eq!(buckets[1].end, NaiveDate::from_ymd(2019, 8, 9));
+        assert_eq!(buckets[1].rates[0].1, Decimal::from_str("1.6").unwrap());
+    }
+}
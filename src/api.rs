@@ -1,20 +1,39 @@
-use crate::db::Db;
-use crate::error::Error;
+use crate::aggregate::{self, Aggregation, Group};
+use crate::caching::{self, Caching, Conditions, Freshness};
+use crate::db::{Db, Direction};
+use crate::error::{self, Error};
 use crate::fetcher::Date;
+use crate::iso4217;
+use crate::legacy;
+use crate::metrics::Metrics;
+use crate::stats;
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::str::FromStr;
 use std::sync::Arc;
 
-use chrono::NaiveDate;
-use serde::Deserialize;
+use chrono::{NaiveDate, Utc};
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
+use warp::http::header::{self, HeaderValue};
+use warp::hyper::body::Bytes;
 use warp::{Filter, Rejection, Reply};
 
-pub fn routes(db: Arc<Db>) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+// largest batch of transactions accepted for conversion, in bytes
+const MAX_BATCH_SIZE: u64 = 16 * 1024 * 1024;
+
+pub fn routes(
+    db: Arc<Db>,
+    cors: warp::cors::Cors,
+    caching: Caching,
+    metrics: Metrics,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
     // /api/v1 endpoint
     let apiv1 = warp::path("api").and(warp::path("v1"));
     let db = warp::any().map(move || db.clone());
+    let caching = caching::conditions().and(warp::any().map(move || caching));
+    let accept = warp::header::optional::<String>("accept");
 
     let latest_head = apiv1
         .and(warp::path("latest"))
@@ -27,6 +46,8 @@ pub fn routes(db: Arc<Db>) -> impl Filter<Extract = (impl Reply,), Error = Rejec
         .and(warp::path::end())
         .and(warp::get())
         .and(warp::query::<Params>())
+        .and(accept)
+        .and(caching.clone())
         .and(db.clone())
         .and_then(latest_handler);
 
@@ -35,53 +56,846 @@ pub fn routes(db: Arc<Db>) -> impl Filter<Extract = (impl Reply,), Error = Rejec
         .and(warp::path::end())
         .and(warp::get())
         .and(warp::query::<Params>())
+        .and(accept)
+        .and(caching.clone())
         .and(db.clone())
         .and_then(history_handler);
 
+    let convert_get = apiv1
+        .and(warp::path("convert"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::query::<ConvertParams>())
+        .and(accept)
+        .and(db.clone())
+        .and_then(convert_handler);
+
+    let fluctuation_get = apiv1
+        .and(warp::path("fluctuation"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::query::<Params>())
+        .and(accept)
+        .and(db.clone())
+        .and_then(fluctuation_handler);
+
+    let stats_get = apiv1
+        .and(warp::path("stats"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::query::<Params>())
+        .and(accept)
+        .and(db.clone())
+        .and_then(stats_handler);
+
+    let currencies_get = apiv1
+        .and(warp::path("currencies"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::query::<Params>())
+        .and(accept)
+        .and(db.clone())
+        .and_then(currencies_handler);
+
+    let convert_batch_post = apiv1
+        .and(warp::path("convert"))
+        .and(warp::path("batch"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(warp::query::<BatchParams>())
+        .and(warp::header::optional::<String>("content-type"))
+        .and(accept)
+        .and(warp::body::content_length_limit(MAX_BATCH_SIZE))
+        .and(warp::body::bytes())
+        .and(db.clone())
+        .and_then(convert_batch_handler);
+
+    let matrix_get = apiv1
+        .and(warp::path("matrix"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::query::<MatrixParams>())
+        .and(accept)
+        .and(db.clone())
+        .and_then(matrix_handler);
+
     let day_get = apiv1
         .and(warp::path::param::<NaiveDate>())
         .and(warp::path::end())
         .and(warp::get())
         .and(warp::query::<Params>())
+        .and(accept)
+        .and(caching)
         .and(db)
         .and_then(day_handler);
 
-    latest_head.or(history_get).or(latest_get).or(day_get)
+    // the cors filter answers preflight requests before the routes match, so it's only applied
+    // under /api/v1
+    let in_apiv1 = warp::path::peek()
+        .and_then(|peek: warp::path::Peek| async move {
+            if peek.segments().take(2).eq(["api", "v1"]) {
+                Ok(())
+            } else {
+                Err(warp::reject::not_found())
+            }
+        })
+        .untuple_one();
+
+    // the format of the responses comes from the Accept header unless the format param is set,
+    // shared caches must then keep a response per Accept header
+    let negotiated = warp::query::<FormatParam>().map(|param: FormatParam| param.format.is_none());
+
+    in_apiv1.and(
+        negotiated
+            .and(
+                latest_head
+                    .or(history_get)
+                    .or(latest_get)
+                    .or(convert_get)
+                    .or(convert_batch_post)
+                    .or(fluctuation_get)
+                    .or(stats_get)
+                    .or(currencies_get)
+                    .or(matrix_get)
+                    .or(day_get),
+            )
+            .map(|negotiated: bool, reply| {
+                if negotiated {
+                    vary(reply, "accept")
+                } else {
+                    Reply::into_response(reply)
+                }
+            })
+            // errors are replied to here so that they get the CORS headers too, browsers don't
+            // let scripts read cross-origin responses without them
+            .recover(move |err: Rejection| {
+                metrics.observe_rejection(&err);
+                error::recover(err)
+            })
+            .with(cors)
+            // the CORS headers echo the request's Origin, preflight responses included
+            .map(|reply| vary(reply, "origin")),
+    )
 }
 
-#[derive(Default, Debug, Deserialize)]
+// tell shared caches the response depends on the request `header`
+fn vary(reply: impl Reply, header: &'static str) -> warp::reply::Response {
+    let mut response = reply.into_response();
+    response
+        .headers_mut()
+        .append(header::VARY, HeaderValue::from_static(header));
+    response
+}
+
+#[derive(Debug, Deserialize)]
+struct FormatParam {
+    format: Option<String>,
+}
+
+#[derive(Default, Debug, Deserialize, Serialize)]
 struct Params {
     start_at: Option<String>,
     end_at: Option<String>,
     base: Option<String>,
     symbols: Option<String>,
+    fallback: Option<String>,
+    format: Option<String>,
+    order: Option<String>,
+    precision: Option<String>,
+    rounding: Option<String>,
+    group: Option<String>,
+    agg: Option<String>,
+    strict: Option<String>,
 }
 
-async fn latest_handler(params: Params, db: Arc<Db>) -> Result<impl Reply, Rejection> {
-    let currencies = db.get_current_rates().await?;
+#[derive(Default, Debug, Deserialize)]
+struct BatchParams {
+    fallback: Option<String>,
+    format: Option<String>,
+    precision: Option<String>,
+    rounding: Option<String>,
+}
 
-    try_reply(vec![currencies], params)
+// transaction of a batch to convert, `date` defaults to the current rates
+#[derive(Debug, PartialEq)]
+struct Transaction {
+    id: serde_json::Value,
+    date: Option<String>,
+    amount: String,
+    from: String,
+    to: String,
+}
+
+#[derive(Deserialize)]
+struct JsonTransaction {
+    #[serde(default)]
+    id: serde_json::Value,
+    date: Option<String>,
+    amount: serde_json::Value,
+    from: String,
+    to: String,
+}
+
+#[derive(Deserialize)]
+struct CsvTransaction {
+    id: String,
+    date: Option<String>,
+    amount: String,
+    from: String,
+    to: String,
+}
+
+// converted amount of a transaction and the date of the rate used
+#[derive(Debug, PartialEq)]
+struct Conversion {
+    rate: Decimal,
+    result: Decimal,
+    rate_date: String,
+}
+
+#[derive(Default, Debug, Deserialize)]
+struct MatrixParams {
+    date: Option<String>,
+    symbols: Option<String>,
+    fallback: Option<String>,
+    format: Option<String>,
+    precision: Option<String>,
+    rounding: Option<String>,
+}
+
+#[derive(Default, Debug, Deserialize)]
+struct ConvertParams {
+    from: Option<String>,
+    to: Option<String>,
+    amount: Option<String>,
+    date: Option<String>,
+    fallback: Option<String>,
+    format: Option<String>,
+    precision: Option<String>,
+    rounding: Option<String>,
+}
+
+// rounding of computed rates and amounts, by default they are kept with all their precision
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Rounding {
+    precision: Option<u32>,
+    strategy: RoundingStrategy,
+}
+
+impl Rounding {
+    // keep all the precision
+    fn exact() -> Rounding {
+        Rounding {
+            precision: None,
+            strategy: RoundingStrategy::MidpointNearestEven,
+        }
+    }
+
+    fn from_params(precision: Option<&str>, rounding: Option<&str>) -> Result<Rounding, Error> {
+        let precision = match precision {
+            Some(precision) => match u32::from_str(precision) {
+                Ok(digits) if digits <= 28 => Some(digits),
+                _ => return Err(Error::InvalidPrecision(precision.to_string())),
+            },
+            None if rounding.is_some() => return Err(Error::MissingParameter("precision")),
+            None => None,
+        };
+
+        let strategy = match rounding {
+            None | Some("half_even") => RoundingStrategy::MidpointNearestEven,
+            Some("half_up") => RoundingStrategy::MidpointAwayFromZero,
+            Some("half_down") => RoundingStrategy::MidpointTowardZero,
+            Some("up") => RoundingStrategy::AwayFromZero,
+            Some("down") => RoundingStrategy::ToZero,
+            Some("ceiling") => RoundingStrategy::ToPositiveInfinity,
+            Some("floor") => RoundingStrategy::ToNegativeInfinity,
+            Some(rounding) => return Err(Error::InvalidRounding(rounding.to_string())),
+        };
+
+        Ok(Rounding {
+            precision,
+            strategy,
+        })
+    }
+
+    fn apply(&self, value: Decimal) -> Decimal {
+        match self.precision {
+            Some(digits) => value.round_dp_with_strategy(digits, self.strategy),
+            None => value.normalize(),
+        }
+    }
+}
+
+// rates of each date, rebased and filtered according to the request params, dates are ascending
+// and every date lists the same currencies in the response order, `None` when a currency has no
+// rate on that date
+type Rates = BTreeMap<String, Vec<(String, Option<Decimal>)>>;
+
+// runs of consecutive dates, first and last, a currency has no rates on, by currency in the
+// response order
+type Gaps = Vec<(String, Vec<(String, String)>)>;
+
+// csv row, the values of its key columns and its rates
+type Row = (Vec<String>, Vec<(String, Option<Decimal>)>);
+
+// response body format, chosen with the `format` parameter or else the `Accept` header
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+enum Format {
+    Json,
+    Csv,
+}
+
+impl Format {
+    fn negotiate(format: Option<&str>, accept: Option<&str>) -> Result<Format, Error> {
+        match format {
+            Some("json") => Ok(Format::Json),
+            Some("csv") => Ok(Format::Csv),
+            Some(format) => Err(Error::InvalidFormat(format.to_string())),
+            None => {
+                let accepted = accept
+                    .into_iter()
+                    .flat_map(|accept| accept.split(','))
+                    .filter_map(|media| {
+                        let mut params = media.split(';');
+                        let name = params.next()?.trim().to_ascii_lowercase();
+                        let quality = params
+                            .find_map(|param| param.trim().strip_prefix("q="))
+                            .map_or(1.0, |q| q.trim().parse::<f32>().unwrap_or(0.0));
+                        Some((name, quality))
+                    })
+                    .collect::<Vec<(String, f32)>>();
+                // the most specific media range that matches applies, RFC 7231 section 5.3.2
+                let quality = |media: &str| {
+                    let range = format!("{}/*", media.split('/').next().unwrap_or_default());
+                    [media, &range, "*/*"]
+                        .iter()
+                        .find_map(|name| accepted.iter().find(|(accepted, _)| accepted == name))
+                        .map_or(0.0, |(_, quality)| *quality)
+                };
+
+                // json unless csv is preferred
+                if quality("text/csv") > quality("application/json") {
+                    Ok(Format::Csv)
+                } else {
+                    Ok(Format::Json)
+                }
+            }
+        }
+    }
+}
+
+// what to do when there are no rates published for a requested day, e.g. weekends and TARGET
+// closing days
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Fallback {
+    None,
+    Previous,
+    Next,
+}
+
+impl Fallback {
+    fn from_param(fallback: Option<&str>) -> Result<Fallback, Error> {
+        match fallback {
+            None | Some("none") => Ok(Fallback::None),
+            Some("previous") => Ok(Fallback::Previous),
+            Some("next") => Ok(Fallback::Next),
+            Some(fallback) => Err(Error::InvalidFallback(fallback.to_string())),
+        }
+    }
+}
+
+// get the rates for `date`, or for the nearest published date according to `fallback`
+async fn find_day_rates(db: &Db, date: NaiveDate, fallback: Fallback) -> Result<Date, Error> {
+    let day = date.to_string();
+    let rates = match fallback {
+        Fallback::None => db.get_day_rates(&day).await?,
+        Fallback::Previous => db.get_nearest_day_rates(&day, Direction::Backward).await?,
+        Fallback::Next => db.get_nearest_day_rates(&day, Direction::Forward).await?,
+    };
+
+    rates.ok_or(Error::DateNotFound(day))
+}
+
+// get the rates for the `date` param, and the date requested, or else the current rates
+async fn find_date_param_rates(
+    db: &Db,
+    date: Option<&str>,
+    fallback: Fallback,
+) -> Result<(Date, Option<NaiveDate>), Error> {
+    match date {
+        Some(date) => {
+            let date = NaiveDate::from_str(date)
+                .map_err(move |_| Error::InvalidDateFormat("date", date.to_string()))?;
+
+            if date < NaiveDate::from_ymd(1999, 1, 4) {
+                return Err(Error::PastDate("date"));
+            }
+
+            Ok((find_day_rates(db, date, fallback).await?, Some(date)))
+        }
+        None => Ok((db.get_current_rates().await?, None)),
+    }
+}
+
+// caching validators and lifetime of a response built from `dates`, up to the `last_day` requested,
+// `current` being the date of the current rates
+fn freshness(
+    dates: &[Date],
+    variant: (&str, &Params, Format),
+    last_day: NaiveDate,
+    current: NaiveDate,
+    caching: &Caching,
+) -> Option<Freshness> {
+    Freshness::new(dates, &variant, last_day, current, Utc::now(), caching)
+}
+
+fn cached_reply<F>(
+    freshness: Option<Freshness>,
+    conditions: Conditions,
+    build: F,
+) -> Result<warp::reply::Response, Rejection>
+where
+    F: FnOnce() -> Result<warp::reply::Response, Rejection>,
+{
+    match freshness {
+        Some(freshness) => freshness.reply(&conditions, build),
+        None => build(),
+    }
+}
+
+async fn latest_handler(
+    params: Params,
+    accept: Option<String>,
+    conditions: Conditions,
+    caching: Caching,
+    db: Arc<Db>,
+) -> Result<impl Reply, Rejection> {
+    let format = Format::negotiate(params.format.as_deref(), accept.as_deref())?;
+    let currencies = vec![db.get_current_rates().await?];
+    let current = currencies[0].value_as_date()?;
+
+    let freshness = freshness(
+        &currencies,
+        ("latest", &params, format),
+        current,
+        current,
+        &caching,
+    );
+    cached_reply(freshness, conditions, || {
+        try_reply(currencies, params, format)
+    })
 }
 
 async fn day_handler(
     date: NaiveDate,
     params: Params,
+    accept: Option<String>,
+    conditions: Conditions,
+    caching: Caching,
     db: Arc<Db>,
 ) -> Result<impl Reply, Rejection> {
     if date < NaiveDate::from_ymd(1999, 1, 4) {
         return Err(Error::PastDate("date").into());
     }
 
-    let currencies = db
-        .get_day_rates(&date.to_string())
-        .await?
-        .ok_or_else(move || Error::DateNotFound(date.to_string()))?;
+    let format = Format::negotiate(params.format.as_deref(), accept.as_deref())?;
+    let fallback = Fallback::from_param(params.fallback.as_deref())?;
+    let currencies = vec![find_day_rates(&db, date, fallback).await?];
+    let requested = date.to_string();
+
+    let current = db.get_current_rates().await?.value_as_date()?;
+    let variant = (requested.as_str(), &params, format);
+    let freshness = freshness(&currencies, variant, date, current, &caching);
+    cached_reply(freshness, conditions, || {
+        let requested_date = Some(requested).filter(|_| fallback != Fallback::None);
+        if format == Format::Csv {
+            return csv_dates_reply(currencies, &params, requested_date.as_deref());
+        }
+
+        let mut response = build_response(currencies, params)?;
+        if let Some(requested_date) = requested_date {
+            response["requested_date"] = json!(requested_date);
+        }
+
+        Ok(warp::reply::json(&response).into_response())
+    })
+}
+
+async fn convert_handler(
+    params: ConvertParams,
+    accept: Option<String>,
+    db: Arc<Db>,
+) -> Result<impl Reply, Rejection> {
+    let format = Format::negotiate(params.format.as_deref(), accept.as_deref())?;
+    let from = params.from.ok_or(Error::MissingParameter("from"))?;
+    let to = params.to.ok_or(Error::MissingParameter("to"))?;
+    let amount = params.amount.ok_or(Error::MissingParameter("amount"))?;
+    let amount = Decimal::from_str(&amount).map_err(move |_| Error::InvalidAmount(amount))?;
+    let fallback = Fallback::from_param(params.fallback.as_deref())?;
+    let rounding = Rounding::from_params(params.precision.as_deref(), params.rounding.as_deref())?;
+
+    let (date, requested_date) =
+        find_date_param_rates(&db, params.date.as_deref(), fallback).await?;
+
+    let date = legacy::derive(date, &[&from, &to]);
+    let rate = cross_rate(&date, &from, &to)?;
+    let result = amount
+        .checked_mul(rate)
+        .ok_or_else(|| Error::InvalidAmount(amount.to_string()))?;
+    let (rate, result) = (rounding.apply(rate), rounding.apply(result));
+    let requested_date = requested_date.filter(|_| fallback != Fallback::None);
+
+    if format == Format::Csv {
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        let mut header = vec!["date", "from", "to", "amount", "rate", "result"];
+        let mut record = vec![
+            date.value,
+            from,
+            to,
+            amount.to_string(),
+            rate.to_string(),
+            result.to_string(),
+        ];
+        if let Some(requested_date) = requested_date {
+            header.push("requested_date");
+            record.push(requested_date.to_string());
+        }
+        writer.write_record(&header).map_err(Error::Csv)?;
+        writer.write_record(&record).map_err(Error::Csv)?;
+        return Ok(csv_reply(writer)?);
+    }
+
+    let mut response = json!({
+        "from": from,
+        "to": to,
+        "amount": decimal_json(amount),
+        "rate": decimal_json(rate),
+        "result": decimal_json(result),
+        "date": date.value,
+    });
+    if let Some(requested_date) = requested_date {
+        response["requested_date"] = json!(requested_date.to_string());
+    }
+
+    Ok(warp::reply::json(&response).into_response())
+}
+
+async fn convert_batch_handler(
+    params: BatchParams,
+    content_type: Option<String>,
+    accept: Option<String>,
+    body: Bytes,
+    db: Arc<Db>,
+) -> Result<impl Reply, Rejection> {
+    let format = Format::negotiate(params.format.as_deref(), accept.as_deref())?;
+    let fallback = Fallback::from_param(params.fallback.as_deref())?;
+    let rounding = Rounding::from_params(params.precision.as_deref(), params.rounding.as_deref())?;
+    let transactions = parse_batch(&body, content_type.as_deref())?;
+
+    // read all the dates in one scan, plus the nearest ones outside of it to fall back to
+    let days = transactions
+        .iter()
+        .filter_map(|transaction| transaction.date.as_deref())
+        .filter_map(|date| NaiveDate::from_str(date).ok())
+        .filter(|date| *date >= NaiveDate::from_ymd(1999, 1, 4))
+        .collect::<BTreeSet<NaiveDate>>();
+    let mut dates = BTreeMap::new();
+    if let (Some(first), Some(last)) = (days.iter().next(), days.iter().next_back()) {
+        let mut found = db.get_range_rates(*first, *last).await?;
+        let outside = match fallback {
+            Fallback::None => None,
+            Fallback::Previous => {
+                db.get_nearest_day_rates(&first.to_string(), Direction::Backward)
+                    .await?
+            }
+            Fallback::Next => {
+                db.get_nearest_day_rates(&last.to_string(), Direction::Forward)
+                    .await?
+            }
+        };
+        found.extend(outside);
+        for date in found {
+            dates.insert(date.value_as_date()?, date);
+        }
+    }
+    let current = match transactions.iter().any(|t| t.date.is_none()) {
+        true => Some(db.get_current_rates().await?),
+        false => None,
+    };
+
+    let conversions = transactions
+        .into_iter()
+        .map(|transaction| {
+            let conversion =
+                convert_transaction(&transaction, &dates, current.as_ref(), fallback, rounding);
+            (transaction, conversion)
+        })
+        .collect();
+
+    batch_reply(conversions, format)
+}
+
+// parse a batch of transactions from a csv body, when the content type says so, or else json
+fn parse_batch(body: &[u8], content_type: Option<&str>) -> Result<Vec<Transaction>, Error> {
+    let is_csv = content_type
+        .and_then(|content_type| content_type.split(';').next())
+        .map(str::trim)
+        == Some("text/csv");
+
+    if is_csv {
+        csv::Reader::from_reader(body)
+            .deserialize::<CsvTransaction>()
+            .map(|row| {
+                let row = row.map_err(|err| Error::InvalidBatch(err.to_string()))?;
+                Ok(Transaction {
+                    id: json!(row.id),
+                    date: row.date,
+                    amount: row.amount,
+                    from: row.from,
+                    to: row.to,
+                })
+            })
+            .collect()
+    } else {
+        serde_json::from_slice::<Vec<JsonTransaction>>(body)
+            .map_err(|err| Error::InvalidBatch(err.to_string()))?
+            .into_iter()
+            .map(|row| {
+                let amount = match row.amount {
+                    serde_json::Value::Number(number) => number.to_string(),
+                    serde_json::Value::String(amount) => amount,
+                    amount => amount.to_string(),
+                };
+                Ok(Transaction {
+                    id: row.id,
+                    date: row.date,
+                    amount,
+                    from: row.from,
+                    to: row.to,
+                })
+            })
+            .collect()
+    }
+}
+
+// convert a transaction with the rates of its date, looked up on `dates`, or the current rates
+fn convert_transaction(
+    transaction: &Transaction,
+    dates: &BTreeMap<NaiveDate, Date>,
+    current: Option<&Date>,
+    fallback: Fallback,
+    rounding: Rounding,
+) -> Result<Conversion, Error> {
+    let amount = Decimal::from_str(&transaction.amount)
+        .map_err(|_| Error::InvalidAmount(transaction.amount.clone()))?;
+
+    let date = match transaction.date {
+        Some(ref date) => {
+            let day = NaiveDate::from_str(date)
+                .map_err(|_| Error::InvalidDateFormat("date", date.to_string()))?;
+            if day < NaiveDate::from_ymd(1999, 1, 4) {
+                return Err(Error::PastDate("date"));
+            }
+            let found = match fallback {
+                Fallback::None => dates.get(&day),
+                Fallback::Previous => dates.range(..=day).next_back().map(|(_, date)| date),
+                Fallback::Next => dates.range(day..).next().map(|(_, date)| date),
+            };
+            found.ok_or_else(|| Error::DateNotFound(date.to_string()))?
+        }
+        None => current.ok_or_else(|| Error::DateNotFound("current".into()))?,
+    };
+
+    let date = legacy::derive(date.clone(), &[&transaction.from, &transaction.to]);
+    let rate = cross_rate(&date, &transaction.from, &transaction.to)?;
+    let result = amount
+        .checked_mul(rate)
+        .ok_or_else(|| Error::InvalidAmount(transaction.amount.clone()))?;
+
+    Ok(Conversion {
+        rate: rounding.apply(rate),
+        result: rounding.apply(result),
+        rate_date: date.value,
+    })
+}
+
+// one entry per transaction in the batch order, transactions that couldn't be converted have an
+// error instead of the result
+fn batch_reply(
+    conversions: Vec<(Transaction, Result<Conversion, Error>)>,
+    format: Format,
+) -> Result<warp::reply::Response, Rejection> {
+    match format {
+        Format::Json => {
+            let results = conversions
+                .into_iter()
+                .map(|(transaction, conversion)| {
+                    let mut entry = json!({
+                        "id": transaction.id,
+                        "date": transaction.date,
+                        "from": transaction.from,
+                        "to": transaction.to,
+                        "amount": Decimal::from_str(&transaction.amount)
+                            .map(decimal_json)
+                            .unwrap_or_else(|_| json!(transaction.amount)),
+                    });
+                    match conversion {
+                        Ok(conversion) => {
+                            entry["rate"] = decimal_json(conversion.rate);
+                            entry["result"] = decimal_json(conversion.result);
+                            entry["rate_date"] = json!(conversion.rate_date);
+                        }
+                        Err(err) => entry["error"] = json!(err.to_string()),
+                    }
+                    entry
+                })
+                .collect::<Vec<serde_json::Value>>();
+            Ok(warp::reply::json(&json!({ "results": results })).into_response())
+        }
+        Format::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            writer
+                .write_record([
+                    "id",
+                    "date",
+                    "from",
+                    "to",
+                    "amount",
+                    "rate",
+                    "result",
+                    "rate_date",
+                    "error",
+                ])
+                .map_err(Error::Csv)?;
+            for (transaction, conversion) in conversions {
+                let id = match transaction.id {
+                    serde_json::Value::String(id) => id,
+                    serde_json::Value::Null => String::new(),
+                    id => id.to_string(),
+                };
+                let (rate, result, rate_date, error) = match conversion {
+                    Ok(conversion) => (
+                        conversion.rate.to_string(),
+                        conversion.result.to_string(),
+                        conversion.rate_date,
+                        String::new(),
+                    ),
+                    Err(err) => (String::new(), String::new(), String::new(), err.to_string()),
+                };
+                writer
+                    .write_record([
+                        id,
+                        transaction.date.unwrap_or_default(),
+                        transaction.from,
+                        transaction.to,
+                        transaction.amount,
+                        rate,
+                        result,
+                        rate_date,
+                        error,
+                    ])
+                    .map_err(Error::Csv)?;
+            }
+            Ok(csv_reply(writer)?)
+        }
+    }
+}
+
+async fn matrix_handler(
+    params: MatrixParams,
+    accept: Option<String>,
+    db: Arc<Db>,
+) -> Result<impl Reply, Rejection> {
+    let format = Format::negotiate(params.format.as_deref(), accept.as_deref())?;
+    let fallback = Fallback::from_param(params.fallback.as_deref())?;
+    let (date, requested_date) =
+        find_date_param_rates(&db, params.date.as_deref(), fallback).await?;
+    let requested_date = requested_date.filter(|_| fallback != Fallback::None);
+
+    matrix_reply(date, requested_date, params, format)
+}
+
+async fn history_handler(
+    params: Params,
+    accept: Option<String>,
+    conditions: Conditions,
+    caching: Caching,
+    db: Arc<Db>,
+) -> Result<impl Reply, Rejection> {
+    let format = Format::negotiate(params.format.as_deref(), accept.as_deref())?;
+    let (start_at, end_at) = date_boundaries(&params)?;
+    let currencies = db.get_range_rates(start_at, end_at).await?;
+
+    if params.group.is_none() && params.agg.is_some() {
+        return Err(Error::MissingParameter("group").into());
+    }
+    let group = params.group.as_deref().map(Group::from_param).transpose()?;
+    let agg = Aggregation::from_param(params.agg.as_deref())?;
+
+    let current = db.get_current_rates().await?.value_as_date()?;
+    let freshness = freshness(
+        &currencies,
+        ("history", &params, format),
+        end_at,
+        current,
+        &caching,
+    );
+    cached_reply(freshness, conditions, || match group {
+        Some(group) => grouped_reply(currencies, params, format, group, agg),
+        None => try_reply(currencies, params, format),
+    })
+}
+
+async fn fluctuation_handler(
+    params: Params,
+    accept: Option<String>,
+    db: Arc<Db>,
+) -> Result<impl Reply, Rejection> {
+    let format = Format::negotiate(params.format.as_deref(), accept.as_deref())?;
+    let (start_at, end_at) = date_boundaries(&params)?;
+
+    // the first and last published dates within the boundaries
+    let start = db
+        .get_nearest_day_rates(&start_at.to_string(), Direction::Forward)
+        .await?;
+    let end = db
+        .get_nearest_day_rates(&end_at.to_string(), Direction::Backward)
+        .await?;
+    let (start, end) = match (start, end) {
+        (Some(start), Some(end)) if start.value <= end.value => (start, end),
+        _ => return Err(Error::DateNotFound(start_at.to_string()).into()),
+    };
 
-    try_reply(vec![currencies], params)
+    fluctuation_reply(start, end, params, format)
 }
 
-async fn history_handler(params: Params, db: Arc<Db>) -> Result<impl Reply, Rejection> {
-    let (start_at, end_at) = match params {
+async fn stats_handler(
+    params: Params,
+    accept: Option<String>,
+    db: Arc<Db>,
+) -> Result<impl Reply, Rejection> {
+    let format = Format::negotiate(params.format.as_deref(), accept.as_deref())?;
+    let (start_at, end_at) = date_boundaries(&params)?;
+    let currencies = db.get_range_rates(start_at, end_at).await?;
+
+    stats_reply(currencies, params, format)
+}
+
+async fn currencies_handler(
+    params: Params,
+    accept: Option<String>,
+    db: Arc<Db>,
+) -> Result<impl Reply, Rejection> {
+    let format = Format::negotiate(params.format.as_deref(), accept.as_deref())?;
+    let spans = db.currency_spans().await?;
+    let current = db.get_current_rates().await?;
+
+    currencies_reply(spans, &current.value, format)
+}
+
+// parse and validate the `start_at` and `end_at` params
+fn date_boundaries(params: &Params) -> Result<(NaiveDate, NaiveDate), Error> {
+    match params {
         Params {
             start_at: Some(ref start_at),
             end_at: Some(ref end_at),
@@ -94,77 +908,500 @@ async fn history_handler(params: Params, db: Arc<Db>) -> Result<impl Reply, Reje
                 .map_err(move |_| Error::InvalidDateFormat("end_at", end_at.to_string()))?;
 
             if start_at < NaiveDate::from_ymd(1999, 1, 4) {
-                return Err(Error::PastDate("start_at").into());
+                return Err(Error::PastDate("start_at"));
             }
 
             if end_at < start_at {
-                return Err(Error::InvalidDateRange.into());
+                return Err(Error::InvalidDateRange);
             }
 
-            (start_at, end_at)
+            Ok((start_at, end_at))
         }
-        _ => return Err(Error::MissingDateBoundaries.into()),
-    };
+        _ => Err(Error::MissingDateBoundaries),
+    }
+}
 
-    let currencies = db.get_range_rates(start_at, end_at).await?;
+// rate of `base` against EUR on the given date, used to rebase all the other rates of that date
+fn base_rate(date: &Date, base: &str) -> Result<Decimal, Error> {
+    date.currencies
+        .iter()
+        .find(|b| b.name == base)
+        .map(|b| b.rate)
+        .ok_or_else(|| Error::InvalidBase(base.to_string()))
+}
+
+// how many units of `to` one unit of `from` buys on the given date
+fn cross_rate(date: &Date, from: &str, to: &str) -> Result<Decimal, Error> {
+    let base_rate = base_rate(date, from)?;
+    let rate = date
+        .currencies
+        .iter()
+        .find(|c| c.name == to)
+        .map(|c| c.rate)
+        .ok_or(Error::InvalidSymbol)?;
 
-    try_reply(currencies, params)
+    rate.checked_div(base_rate)
+        .ok_or_else(|| Error::InvalidBase(from.to_string()))
 }
 
-fn try_reply(dates: Vec<Date>, params: Params) -> Result<impl Reply, Rejection> {
-    let first = dates.get(0).ok_or(Error::EmpyDataset)?;
+fn try_reply(
+    dates: Vec<Date>,
+    params: Params,
+    format: Format,
+) -> Result<warp::reply::Response, Rejection> {
+    match format {
+        Format::Json => {
+            let response = build_response(dates, params)?;
+            Ok(warp::reply::json(&response).into_response())
+        }
+        Format::Csv => csv_dates_reply(dates, &params, None),
+    }
+}
 
-    let symbols = match params.symbols {
-        Some(symbols_params) => {
-            let symbols = symbols_params
-                .split(',')
-                .map(String::from)
-                .collect::<Vec<String>>();
-            if !symbols
+// rates as csv, a row per date, along with the date requested when a fallback is used
+fn csv_dates_reply(
+    dates: Vec<Date>,
+    params: &Params,
+    requested_date: Option<&str>,
+) -> Result<warp::reply::Response, Rejection> {
+    let rounding = Rounding::from_params(params.precision.as_deref(), params.rounding.as_deref())?;
+    let mut keys = vec!["date"];
+    keys.extend(requested_date.map(|_| "requested_date"));
+    let rows = rebase(dates, params, rounding)?
+        .into_iter()
+        .map(|(date, currencies)| {
+            let mut values = vec![date];
+            values.extend(requested_date.map(String::from));
+            (values, currencies)
+        })
+        .collect();
+    let writer = rates_csv(&keys, rows, &currency_order(params))?;
+    Ok(csv_reply(writer)?)
+}
+
+// rates aggregated by period, one entry per period
+fn grouped_reply(
+    dates: Vec<Date>,
+    params: Params,
+    format: Format,
+    group: Group,
+    agg: Aggregation,
+) -> Result<warp::reply::Response, Rejection> {
+    let rounding = Rounding::from_params(params.precision.as_deref(), params.rounding.as_deref())?;
+    // aggregate exact rates and only round the results
+    let rates = rebase(dates, &params, Rounding::exact())?;
+    let gaps = gaps(&rates);
+    let columns = columns(&rates);
+    let buckets = aggregate::aggregate(&known_rates(&rates), group, agg)?
+        .into_iter()
+        .map(|bucket| {
+            // currencies without rates in the whole period are missing
+            let rates = columns
                 .iter()
-                .all(|s| first.currencies.iter().any(|c| &c.name == s))
-            {
-                return Err(Error::InvalidSymbol.into());
+                .map(|column| {
+                    let rate = bucket
+                        .rates
+                        .iter()
+                        .find(|(name, _)| name == column)
+                        .map(|(_, rate)| rounding.apply(*rate));
+                    (column.clone(), rate)
+                })
+                .collect::<Vec<(String, Option<Decimal>)>>();
+            (bucket.start.to_string(), bucket.end.to_string(), rates)
+        })
+        .collect::<Vec<_>>();
+
+    match format {
+        Format::Json => {
+            let rates = buckets
+                .into_iter()
+                .map(|(start, end, rates)| {
+                    json!({
+                        "start": start,
+                        "end": end,
+                        "rates": currencies_json(rates),
+                    })
+                })
+                .collect::<Vec<serde_json::Value>>();
+            let mut response = json!({
+                "rates": rates,
+                "base": params.base.as_deref().unwrap_or("EUR"),
+                "start_at": params.start_at,
+                "end_at": params.end_at,
+                "group": group.as_str(),
+                "agg": agg.as_str(),
+            });
+            if !gaps.is_empty() {
+                response["gaps"] = gaps_json(gaps);
             }
-            symbols
+            Ok(warp::reply::json(&response).into_response())
+        }
+        Format::Csv => {
+            let rows = buckets
+                .into_iter()
+                .map(|(start, end, rates)| (vec![start, end], rates))
+                .collect();
+            let writer = rates_csv(&["start", "end"], rows, &currency_order(&params))?;
+            Ok(csv_reply(writer)?)
+        }
+    }
+}
+
+// cross rates between every pair of symbols, rows are the base and columns the quoted currency
+fn matrix_reply(
+    date: Date,
+    requested_date: Option<NaiveDate>,
+    params: MatrixParams,
+    format: Format,
+) -> Result<warp::reply::Response, Rejection> {
+    let rounding = Rounding::from_params(params.precision.as_deref(), params.rounding.as_deref())?;
+    let mut symbols = match params.symbols {
+        Some(ref symbols) => symbols.split(',').collect::<Vec<&str>>(),
+        None => {
+            let mut names = date
+                .currencies
+                .iter()
+                .map(|c| c.name.as_str())
+                .collect::<Vec<&str>>();
+            names.sort_unstable();
+            names
         }
-        None => Vec::new(),
     };
+    // keep the first occurrence of repeated symbols
+    let mut seen = BTreeSet::new();
+    symbols.retain(|symbol| seen.insert(*symbol));
 
-    let mut rates = HashMap::new();
+    let date = legacy::derive(date.clone(), &symbols);
+    if !symbols
+        .iter()
+        .all(|symbol| date.currencies.iter().any(|c| c.name == *symbol))
+    {
+        return Err(Error::InvalidSymbol.into());
+    }
 
-    for date in dates.into_iter() {
-        let mut currencies = HashMap::new();
+    let matrix = symbols
+        .iter()
+        .map(|from| {
+            let row = symbols
+                .iter()
+                .map(|to| {
+                    Ok((
+                        to.to_string(),
+                        Some(rounding.apply(cross_rate(&date, from, to)?)),
+                    ))
+                })
+                .collect::<Result<Vec<(String, Option<Decimal>)>, Error>>()?;
+            Ok((from.to_string(), row))
+        })
+        .collect::<Result<Vec<(String, Vec<(String, Option<Decimal>)>)>, Error>>()?;
 
-        let base_rate = match params.base {
-            None => 1.0,
-            Some(ref base) => date
-                .currencies
+    match format {
+        Format::Json => {
+            let rates = matrix
+                .into_iter()
+                .map(|(from, row)| (from, currencies_json(row)))
+                .collect::<serde_json::Map<String, serde_json::Value>>();
+            let mut response = json!({
+                "rates": rates,
+                "date": date.value,
+            });
+            if let Some(requested_date) = requested_date {
+                response["requested_date"] = json!(requested_date.to_string());
+            }
+            Ok(warp::reply::json(&response).into_response())
+        }
+        Format::Csv => {
+            let rows = matrix
+                .into_iter()
+                .map(|(from, row)| (vec![from], row))
+                .collect();
+            let order = symbols
                 .iter()
-                .find(|b| &b.name == base)
-                .map(|b| b.rate)
-                .ok_or_else(|| Error::InvalidBase(base.to_string()))?,
+                .map(|symbol| symbol.to_string())
+                .collect::<Vec<String>>();
+            let writer = rates_csv(&["base"], rows, &order)?;
+            Ok(csv_reply(writer)?)
+        }
+    }
+}
+
+// change of each rate between the `start` and `end` dates
+fn fluctuation_reply(
+    start: Date,
+    end: Date,
+    params: Params,
+    format: Format,
+) -> Result<warp::reply::Response, Rejection> {
+    let rounding = Rounding::from_params(params.precision.as_deref(), params.rounding.as_deref())?;
+    let (start_at, end_at) = (start.value.clone(), end.value.clone());
+    // compute the changes on exact rates and only round the results
+    let mut rates = rebase(vec![start, end], &params, Rounding::exact())?;
+    let gaps = gaps(&rates);
+    let start_rates = rates.remove(&start_at).unwrap_or_default();
+    let end_rates = rates.remove(&end_at).unwrap_or_else(|| start_rates.clone());
+
+    let mut fluctuations = Vec::new();
+    // both dates list the same currencies in the same order
+    for ((name, start_rate), (_, end_rate)) in start_rates.into_iter().zip(end_rates) {
+        let values = match (start_rate, end_rate) {
+            (Some(start_rate), Some(end_rate)) => {
+                let change = end_rate - start_rate;
+                let change_pct = change
+                    .checked_div(start_rate)
+                    .and_then(|ratio| ratio.checked_mul(Decimal::ONE_HUNDRED))
+                    .ok_or_else(|| Error::InvalidBase(params.base.clone().unwrap_or_default()))?;
+                [start_rate, end_rate, change, change_pct].map(|value| Some(rounding.apply(value)))
+            }
+            // the currency is missing on one of the dates
+            (start_rate, end_rate) => [
+                start_rate.map(|value| rounding.apply(value)),
+                end_rate.map(|value| rounding.apply(value)),
+                None,
+                None,
+            ],
         };
+        fluctuations.push((name, values));
+    }
 
-        for currency in date.currencies.into_iter() {
-            if symbols.is_empty() || symbols.contains(&currency.name) {
-                currencies.insert(currency.name, currency.rate / base_rate);
+    match format {
+        Format::Json => {
+            let rates = fluctuations
+                .into_iter()
+                .map(|(name, [start_rate, end_rate, change, change_pct])| {
+                    let fluctuation = json!({
+                        "start_rate": start_rate.map(decimal_json),
+                        "end_rate": end_rate.map(decimal_json),
+                        "change": change.map(decimal_json),
+                        "change_pct": change_pct.map(decimal_json),
+                    });
+                    (name, fluctuation)
+                })
+                .collect::<serde_json::Map<String, serde_json::Value>>();
+            let mut response = json!({
+                "rates": rates,
+                "base": params.base.as_deref().unwrap_or("EUR"),
+                "start_at": start_at,
+                "end_at": end_at,
+            });
+            if !gaps.is_empty() {
+                response["gaps"] = gaps_json(gaps);
+            }
+            Ok(warp::reply::json(&response).into_response())
+        }
+        Format::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            writer
+                .write_record(["currency", "start_rate", "end_rate", "change", "change_pct"])
+                .map_err(Error::Csv)?;
+            for (name, values) in fluctuations {
+                writer
+                    .write_record(
+                        std::iter::once(name).chain(
+                            values.iter().map(|value| {
+                                value.map(|value| value.to_string()).unwrap_or_default()
+                            }),
+                        ),
+                    )
+                    .map_err(Error::Csv)?;
             }
+            Ok(csv_reply(writer)?)
         }
+    }
+}
 
-        rates.insert(date.value, currencies);
+// descriptive statistics of each rate over the dates
+fn stats_reply(
+    dates: Vec<Date>,
+    params: Params,
+    format: Format,
+) -> Result<warp::reply::Response, Rejection> {
+    let rounding = Rounding::from_params(params.precision.as_deref(), params.rounding.as_deref())?;
+    // compute the statistics on exact rates and only round the results
+    let rates = rebase(dates, &params, Rounding::exact())?;
+    let (start_at, end_at) = match (rates.keys().next(), rates.keys().next_back()) {
+        (Some(start_at), Some(end_at)) => (start_at.clone(), end_at.clone()),
+        _ => return Err(Error::EmpyDataset.into()),
+    };
+    let gaps = gaps(&rates);
+    let stats = stats::stats(&known_rates(&rates))?;
+
+    match format {
+        Format::Json => {
+            let rates = stats
+                .into_iter()
+                .map(|(name, stats)| {
+                    let stats = json!({
+                        "days": stats.days,
+                        "min": decimal_json(rounding.apply(stats.min)),
+                        "min_date": stats.min_date,
+                        "max": decimal_json(rounding.apply(stats.max)),
+                        "max_date": stats.max_date,
+                        "mean": decimal_json(rounding.apply(stats.mean)),
+                        "median": decimal_json(rounding.apply(stats.median)),
+                        "std_dev": stats.std_dev.map(|value| decimal_json(rounding.apply(value))),
+                        "volatility": stats.volatility.map(|value| decimal_json(rounding.apply(value))),
+                    });
+                    (name, stats)
+                })
+                .collect::<serde_json::Map<String, serde_json::Value>>();
+            let mut response = json!({
+                "rates": rates,
+                "base": params.base.as_deref().unwrap_or("EUR"),
+                "start_at": start_at,
+                "end_at": end_at,
+            });
+            if !gaps.is_empty() {
+                response["gaps"] = gaps_json(gaps);
+            }
+            Ok(warp::reply::json(&response).into_response())
+        }
+        Format::Csv => {
+            let optional = |value: Option<Decimal>| {
+                value
+                    .map(|value| rounding.apply(value).to_string())
+                    .unwrap_or_default()
+            };
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            writer
+                .write_record([
+                    "currency",
+                    "days",
+                    "min",
+                    "min_date",
+                    "max",
+                    "max_date",
+                    "mean",
+                    "median",
+                    "std_dev",
+                    "volatility",
+                ])
+                .map_err(Error::Csv)?;
+            for (name, stats) in stats {
+                writer
+                    .write_record([
+                        name,
+                        stats.days.to_string(),
+                        rounding.apply(stats.min).to_string(),
+                        stats.min_date,
+                        rounding.apply(stats.max).to_string(),
+                        stats.max_date,
+                        rounding.apply(stats.mean).to_string(),
+                        rounding.apply(stats.median).to_string(),
+                        optional(stats.std_dev),
+                        optional(stats.volatility),
+                    ])
+                    .map_err(Error::Csv)?;
+            }
+            Ok(csv_reply(writer)?)
+        }
+    }
+}
+
+// every currency that has ever been published, and the legacy currencies replaced by the euro,
+// with its ISO 4217 metadata, `active` currencies have a rate on the `current` date
+fn currencies_reply(
+    spans: BTreeMap<String, (String, String)>,
+    current: &str,
+    format: Format,
+) -> Result<warp::reply::Response, Rejection> {
+    let codes = spans
+        .keys()
+        .map(String::as_str)
+        .chain(legacy::CURRENCIES.iter().map(|legacy| legacy.code))
+        .collect::<BTreeSet<&str>>();
+    let catalogue = codes.into_iter().map(|code| {
+        let span = spans.get(code);
+        let active = span.is_some_and(|(_, last_date)| last_date == current);
+        (
+            code,
+            iso4217::lookup(code),
+            span,
+            active,
+            legacy::lookup(code),
+        )
+    });
+
+    match format {
+        Format::Json => {
+            let currencies = catalogue
+                .map(|(code, metadata, span, active, legacy)| {
+                    let currency = json!({
+                        "name": metadata.map(|metadata| metadata.name),
+                        "numeric": metadata.map(|metadata| metadata.numeric),
+                        "minor_units": metadata.map(|metadata| metadata.minor_units),
+                        "symbol": metadata.map(|metadata| metadata.symbol),
+                        "first_date": span.map(|(first_date, _)| first_date),
+                        "last_date": span.map(|(_, last_date)| last_date),
+                        "active": active,
+                        "fixed_rate": legacy.map(|legacy| decimal_json(legacy.rate)),
+                        "fixed_since": legacy.map(|legacy| legacy.since),
+                    });
+                    (code.to_string(), currency)
+                })
+                .collect::<serde_json::Map<String, serde_json::Value>>();
+            let response = json!({
+                "currencies": currencies,
+                "date": current,
+            });
+            Ok(warp::reply::json(&response).into_response())
+        }
+        Format::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            writer
+                .write_record([
+                    "code",
+                    "name",
+                    "numeric",
+                    "minor_units",
+                    "symbol",
+                    "first_date",
+                    "last_date",
+                    "active",
+                    "fixed_rate",
+                    "fixed_since",
+                ])
+                .map_err(Error::Csv)?;
+            for (code, metadata, span, active, legacy) in catalogue {
+                writer
+                    .write_record([
+                        code,
+                        metadata.map_or("", |metadata| metadata.name),
+                        metadata.map_or("", |metadata| metadata.numeric),
+                        &metadata
+                            .map_or(String::new(), |metadata| metadata.minor_units.to_string()),
+                        metadata.map_or("", |metadata| metadata.symbol),
+                        span.map_or("", |(first_date, _)| first_date),
+                        span.map_or("", |(_, last_date)| last_date),
+                        &active.to_string(),
+                        &legacy.map_or(String::new(), |legacy| legacy.rate.to_string()),
+                        legacy.map_or("", |legacy| legacy.since),
+                    ])
+                    .map_err(Error::Csv)?;
+            }
+            Ok(csv_reply(writer)?)
+        }
     }
+}
+
+fn build_response(dates: Vec<Date>, params: Params) -> Result<serde_json::Value, Error> {
+    let rounding = Rounding::from_params(params.precision.as_deref(), params.rounding.as_deref())?;
+    let rates = rebase(dates, &params, rounding)?;
+    let gaps = gaps(&rates);
 
     let base = params.base.unwrap_or_else(|| "EUR".to_string());
-    let response = if rates.len() < 2 {
-        // safe to call unwrap as we have already checked dates have at least one element
+    let mut response = if rates.len() < 2 {
+        // safe to call unwrap as rebase already checked dates have at least one element
         let (date, rates) = rates.into_iter().next().unwrap();
         json! ({
-            "rates": rates,
+            "rates": currencies_json(rates),
             "base": base,
             "date": date
         })
     } else {
+        let rates = rates
+            .into_iter()
+            .map(|(date, rates)| (date, currencies_json(rates)))
+            .collect::<serde_json::Map<_, _>>();
         json! ({
             "rates": rates,
             "base": base,
@@ -172,20 +1409,262 @@ fn try_reply(dates: Vec<Date>, params: Params) -> Result<impl Reply, Rejection>
             "end_at": params.end_at,
         })
     };
-    Ok(warp::reply::json(&response))
+    if !gaps.is_empty() {
+        response["gaps"] = gaps_json(gaps);
+    }
+    Ok(response)
+}
+
+// decimals serialize as strings, this keeps them as exact json numbers instead
+fn decimal_json(value: Decimal) -> serde_json::Value {
+    serde_json::from_str(&value.to_string()).expect("decimals are valid json numbers")
+}
+
+fn currencies_json(currencies: Vec<(String, Option<Decimal>)>) -> serde_json::Value {
+    currencies
+        .into_iter()
+        .map(|(name, rate)| (name, json!(rate.map(decimal_json))))
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+// currencies listed on `order`, or else on `symbols`, come first and in that order, the others
+// follow alphabetically
+fn currency_order(params: &Params) -> Vec<String> {
+    params
+        .order
+        .as_deref()
+        .or(params.symbols.as_deref())
+        .map(|order| order.split(',').map(String::from).collect())
+        .unwrap_or_default()
+}
+
+fn order_key<'a>(name: &'a str, order: &[String]) -> (usize, &'a str) {
+    let position = order.iter().position(|o| o == name).unwrap_or(order.len());
+    (position, name)
+}
+
+// one row per entry, e.g. a date, with the entry's `keys` columns followed by a column per
+// currency in the response order
+fn rates_csv(
+    keys: &[&str],
+    rows: Vec<Row>,
+    order: &[String],
+) -> Result<csv::Writer<Vec<u8>>, Error> {
+    let mut columns = rows
+        .iter()
+        .flat_map(|(_, currencies)| currencies.iter().map(|(name, _)| name.as_str()))
+        .collect::<BTreeSet<&str>>()
+        .into_iter()
+        .collect::<Vec<&str>>();
+    columns.sort_by_key(|name| order_key(name, order));
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer
+        .write_record(keys.iter().chain(columns.iter()))
+        .map_err(Error::Csv)?;
+    for (values, currencies) in &rows {
+        let row = columns.iter().map(|symbol| {
+            currencies
+                .iter()
+                .find(|(name, _)| name == symbol)
+                .and_then(|(_, rate)| rate.map(|rate| rate.to_string()))
+                .unwrap_or_default()
+        });
+        writer
+            .write_record(values.iter().cloned().chain(row))
+            .map_err(Error::Csv)?;
+    }
+    Ok(writer)
+}
+
+fn csv_reply(writer: csv::Writer<Vec<u8>>) -> Result<warp::reply::Response, Error> {
+    let body = writer
+        .into_inner()
+        .map_err(|err| Error::Csv(err.into_error().into()))?;
+    Ok(warp::reply::with_header(body, "content-type", "text/csv; charset=utf-8").into_response())
+}
+
+// the currencies listed on each date, in the response order
+fn columns(rates: &Rates) -> Vec<String> {
+    rates
+        .values()
+        .next()
+        .map(|currencies| currencies.iter().map(|(name, _)| name.clone()).collect())
+        .unwrap_or_default()
+}
+
+// the rates of each date without the missing ones
+fn known_rates(rates: &Rates) -> BTreeMap<String, Vec<(String, Decimal)>> {
+    rates
+        .iter()
+        .map(|(date, currencies)| {
+            let currencies = currencies
+                .iter()
+                .filter_map(|(name, rate)| rate.map(|rate| (name.clone(), rate)))
+                .collect();
+            (date.clone(), currencies)
+        })
+        .collect()
+}
+
+fn gaps(rates: &Rates) -> Gaps {
+    columns(rates)
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, name)| {
+            let mut runs: Vec<(String, String)> = Vec::new();
+            let mut previous_missing = false;
+            for (date, currencies) in rates {
+                let missing = currencies[index].1.is_none();
+                match runs.last_mut() {
+                    Some((_, end)) if missing && previous_missing => *end = date.clone(),
+                    _ if missing => runs.push((date.clone(), date.clone())),
+                    _ => (),
+                }
+                previous_missing = missing;
+            }
+            (!runs.is_empty()).then_some((name, runs))
+        })
+        .collect()
+}
+
+fn gaps_json(gaps: Gaps) -> serde_json::Value {
+    gaps.into_iter()
+        .map(|(name, runs)| {
+            let runs = runs
+                .into_iter()
+                .map(|(start_at, end_at)| json!({"start_at": start_at, "end_at": end_at}))
+                .collect::<Vec<serde_json::Value>>();
+            (name, json!(runs))
+        })
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+// describe the gaps, e.g. `HRK on 2023-01-02 to 2023-01-31, 2023-02-03`
+fn describe_gaps(gaps: &Gaps) -> String {
+    gaps.iter()
+        .map(|(name, runs)| {
+            let runs = runs
+                .iter()
+                .map(|(start_at, end_at)| match start_at == end_at {
+                    true => start_at.clone(),
+                    false => format!("{} to {}", start_at, end_at),
+                })
+                .collect::<Vec<String>>();
+            format!("{} on {}", name, runs.join(", "))
+        })
+        .collect::<Vec<String>>()
+        .join("; ")
+}
+
+fn strict_from_param(strict: Option<&str>) -> Result<bool, Error> {
+    match strict {
+        None | Some("false") => Ok(false),
+        Some("true") => Ok(true),
+        Some(strict) => Err(Error::InvalidStrict(strict.to_string())),
+    }
+}
+
+// validate the requested symbols and base against the whole range and rebase the rates of each
+// date to the requested base, currencies missing on a date, or all of them when the base is, are
+// `None` unless `strict` is set, which fails instead
+fn rebase(dates: Vec<Date>, params: &Params, rounding: Rounding) -> Result<Rates, Error> {
+    if dates.is_empty() {
+        return Err(Error::EmpyDataset);
+    }
+    let strict = strict_from_param(params.strict.as_deref())?;
+
+    // legacy currencies are only derived when requested
+    let requested = params
+        .symbols
+        .iter()
+        .flat_map(|symbols| symbols.split(','))
+        .chain(params.base.as_deref())
+        .collect::<Vec<&str>>();
+    let dates = dates
+        .into_iter()
+        .map(|date| legacy::derive(date, &requested))
+        .collect::<Vec<Date>>();
+
+    let published = dates
+        .iter()
+        .flat_map(|date| date.currencies.iter().map(|c| c.name.as_str()))
+        .collect::<BTreeSet<&str>>();
+
+    let mut columns = match params.symbols {
+        Some(ref symbols_params) => {
+            let symbols = symbols_params
+                .split(',')
+                .map(String::from)
+                .collect::<Vec<String>>();
+            if !symbols.iter().all(|s| published.contains(s.as_str())) {
+                return Err(Error::InvalidSymbol);
+            }
+            symbols
+        }
+        None => published.iter().map(|name| name.to_string()).collect(),
+    };
+    if let Some(ref base) = params.base {
+        if !published.contains(base.as_str()) {
+            return Err(Error::InvalidBase(base.to_string()));
+        }
+    }
+
+    let order = currency_order(params);
+    columns.sort_by(|name1, name2| order_key(name1, &order).cmp(&order_key(name2, &order)));
+    columns.dedup();
+    let mut rates = BTreeMap::new();
+
+    for date in dates.into_iter() {
+        let base_rate = match params.base {
+            None => Some(Decimal::ONE),
+            Some(ref base) => base_rate(&date, base).ok(),
+        };
+
+        let mut currencies = Vec::new();
+        for column in &columns {
+            let rate = date.currencies.iter().find(|c| &c.name == column);
+            let rate = match (rate, base_rate) {
+                (Some(currency), Some(base_rate)) => {
+                    let rate = currency.rate.checked_div(base_rate).ok_or_else(|| {
+                        Error::InvalidBase(params.base.clone().unwrap_or_default())
+                    })?;
+                    Some(rounding.apply(rate))
+                }
+                _ => None,
+            };
+            currencies.push((column.clone(), rate));
+        }
+
+        rates.insert(date.value, currencies);
+    }
+
+    if strict {
+        let gaps = gaps(&rates);
+        if !gaps.is_empty() {
+            return Err(Error::MissingRates(describe_gaps(&gaps)));
+        }
+    }
+
+    Ok(rates)
 }
 
 #[cfg(test)]
+#[allow(unused_mut, clippy::field_reassign_with_default)]
 mod tests {
     use super::*;
-    use crate::fetcher::Envelope;
+    use crate::cors::Cors;
+    use crate::fetcher::{Currency, Envelope};
+    use crate::mock_ecb;
     use std::fs::File;
 
     #[test]
     fn try_reply_returns_err_on_empty_dates() {
         let dates = Vec::new();
         let params = Params::default();
-        let reply = try_reply(dates, params);
+        let reply = try_reply(dates, params, Format::Json);
         assert!(reply.is_err());
     }
 
@@ -194,11 +1673,11 @@ mod tests {
         let file = File::open("seed_rates.xml").unwrap();
         let envelope: Envelope = serde_xml_rs::from_reader(&file).unwrap();
         let dates = envelope.cube.dates;
-        let mut rates = HashMap::new();
+        let mut rates = BTreeMap::new();
         for date in dates.clone() {
-            let mut currencies = HashMap::new();
+            let mut currencies = BTreeMap::new();
             for currency in date.currencies {
-                currencies.insert(currency.name, currency.rate);
+                currencies.insert(currency.name, decimal_json(currency.rate.normalize()));
             }
             rates.insert(date.value, currencies);
         }
@@ -208,7 +1687,9 @@ mod tests {
             end_at: Some("2019-10-18".to_string()),
             ..Default::default()
         };
-        let response = try_reply(dates, params).unwrap().into_response();
+        let response = try_reply(dates, params, Format::Json)
+            .unwrap()
+            .into_response();
         let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
         let body_str = String::from_utf8(body.as_ref().to_vec()).unwrap();
         let json = json!({
@@ -225,17 +1706,17 @@ mod tests {
         let file = File::open("seed_rates.xml").unwrap();
         let envelope: Envelope = serde_xml_rs::from_reader(&file).unwrap();
         let mut dates = envelope.cube.dates;
-        let mut rates = HashMap::new();
+        let mut rates = BTreeMap::new();
         for date in dates.clone() {
-            let mut currencies = HashMap::new();
+            let mut currencies = BTreeMap::new();
             for currency in date.currencies {
-                currencies.insert(currency.name, currency.rate);
+                currencies.insert(currency.name, decimal_json(currency.rate.normalize()));
             }
             rates.insert(date.value, currencies);
         }
 
         let params = Params::default();
-        let response = try_reply(vec![dates.pop().unwrap()], params)
+        let response = try_reply(vec![dates.pop().unwrap()], params, Format::Json)
             .unwrap()
             .into_response();
         let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
@@ -254,20 +1735,20 @@ mod tests {
         let file = File::open("seed_rates.xml").unwrap();
         let envelope: Envelope = serde_xml_rs::from_reader(&file).unwrap();
         let mut dates = envelope.cube.dates;
-        let mut rates = HashMap::new();
+        let mut rates = BTreeMap::new();
         for date in dates.clone() {
-            let mut currencies = HashMap::new();
-            for currency in date.currencies {
-                if &currency.name == "USD" || currency.name == "JPY" {
-                    currencies.insert(currency.name, currency.rate);
-                }
+            // in the order of the symbols param
+            let mut currencies = serde_json::Map::new();
+            for symbol in &["USD", "JPY"] {
+                let currency = date.currencies.iter().find(|c| &c.name == symbol).unwrap();
+                currencies.insert(currency.name.clone(), decimal_json(currency.rate));
             }
             rates.insert(date.value, currencies);
         }
 
         let mut params = Params::default();
         params.symbols = Some("USD,JPY".to_string());
-        let response = try_reply(vec![dates.pop().unwrap()], params)
+        let response = try_reply(vec![dates.pop().unwrap()], params, Format::Json)
             .unwrap()
             .into_response();
         let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
@@ -286,13 +1767,13 @@ mod tests {
         let file = File::open("seed_rates.xml").unwrap();
         let envelope: Envelope = serde_xml_rs::from_reader(&file).unwrap();
         let dates = envelope.cube.dates;
-        let mut rates = HashMap::new();
+        let mut rates = BTreeMap::new();
         for date in dates.clone() {
-            let mut currencies = HashMap::new();
-            for currency in date.currencies {
-                if &currency.name == "USD" || currency.name == "JPY" {
-                    currencies.insert(currency.name, currency.rate);
-                }
+            // in the order of the symbols param
+            let mut currencies = serde_json::Map::new();
+            for symbol in &["USD", "JPY"] {
+                let currency = date.currencies.iter().find(|c| &c.name == symbol).unwrap();
+                currencies.insert(currency.name.clone(), decimal_json(currency.rate));
             }
             rates.insert(date.value, currencies);
         }
@@ -301,7 +1782,9 @@ mod tests {
         params.start_at = Some("2019-07-22".to_string());
         params.end_at = Some("2019-10-18".to_string());
         params.symbols = Some("USD,JPY".to_string());
-        let response = try_reply(dates, params).unwrap().into_response();
+        let response = try_reply(dates, params, Format::Json)
+            .unwrap()
+            .into_response();
         let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
         let body_str = String::from_utf8(body.as_ref().to_vec()).unwrap();
         let json = json!({
@@ -318,9 +1801,9 @@ mod tests {
         let file = File::open("seed_rates.xml").unwrap();
         let envelope: Envelope = serde_xml_rs::from_reader(&file).unwrap();
         let mut dates = envelope.cube.dates;
-        let mut rates = HashMap::new();
+        let mut rates = BTreeMap::new();
         for date in dates.clone() {
-            let mut currencies = HashMap::new();
+            let mut currencies = BTreeMap::new();
             for currency in &date.currencies {
                 let rate = date
                     .currencies
@@ -329,14 +1812,17 @@ mod tests {
                     .map(|b| b.rate)
                     .unwrap();
 
-                currencies.insert(currency.name.to_string(), currency.rate / rate);
+                currencies.insert(
+                    currency.name.to_string(),
+                    decimal_json((currency.rate / rate).normalize()),
+                );
             }
             rates.insert(date.value, currencies);
         }
 
         let mut params = Params::default();
         params.base = Some("GBP".to_string());
-        let response = try_reply(vec![dates.pop().unwrap()], params)
+        let response = try_reply(vec![dates.pop().unwrap()], params, Format::Json)
             .unwrap()
             .into_response();
         let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
@@ -355,9 +1841,9 @@ mod tests {
         let file = File::open("seed_rates.xml").unwrap();
         let envelope: Envelope = serde_xml_rs::from_reader(&file).unwrap();
         let dates = envelope.cube.dates;
-        let mut rates = HashMap::new();
+        let mut rates = BTreeMap::new();
         for date in dates.clone() {
-            let mut currencies = HashMap::new();
+            let mut currencies = BTreeMap::new();
             for currency in &date.currencies {
                 let rate = date
                     .currencies
@@ -366,7 +1852,10 @@ mod tests {
                     .map(|b| b.rate)
                     .unwrap();
 
-                currencies.insert(currency.name.to_string(), currency.rate / rate);
+                currencies.insert(
+                    currency.name.to_string(),
+                    decimal_json((currency.rate / rate).normalize()),
+                );
             }
             rates.insert(date.value, currencies);
         }
@@ -375,7 +1864,9 @@ mod tests {
         params.base = Some("GBP".to_string());
         params.start_at = Some("2019-07-22".to_string());
         params.end_at = Some("2019-10-18".to_string());
-        let response = try_reply(dates, params).unwrap().into_response();
+        let response = try_reply(dates, params, Format::Json)
+            .unwrap()
+            .into_response();
         let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
         let body_str = String::from_utf8(body.as_ref().to_vec()).unwrap();
         let json = json!({
@@ -386,4 +1877,997 @@ mod tests {
         });
         assert_eq!(json.to_string(), body_str);
     }
+
+    #[test]
+    fn cross_rate() {
+        let file = File::open("seed_rates.xml").unwrap();
+        let envelope: Envelope = serde_xml_rs::from_reader(&file).unwrap();
+        let date = envelope.cube.dates.first().unwrap();
+        let usd = date.currencies.iter().find(|c| c.name == "USD").unwrap();
+        let jpy = date.currencies.iter().find(|c| c.name == "JPY").unwrap();
+
+        let rate = super::cross_rate(date, "USD", "JPY").unwrap();
+        assert_eq!(rate, jpy.rate / usd.rate);
+
+        assert!(matches!(
+            super::cross_rate(date, "XXX", "JPY"),
+            Err(Error::InvalidBase(_))
+        ));
+        assert!(matches!(
+            super::cross_rate(date, "USD", "XXX"),
+            Err(Error::InvalidSymbol)
+        ));
+    }
+
+    #[test]
+    fn fallback_from_param() {
+        assert_eq!(Fallback::from_param(None).unwrap(), Fallback::None);
+        assert_eq!(Fallback::from_param(Some("none")).unwrap(), Fallback::None);
+        assert_eq!(
+            Fallback::from_param(Some("previous")).unwrap(),
+            Fallback::Previous
+        );
+        assert_eq!(Fallback::from_param(Some("next")).unwrap(), Fallback::Next);
+        assert!(matches!(
+            Fallback::from_param(Some("nearest")),
+            Err(Error::InvalidFallback(_))
+        ));
+    }
+
+    #[test]
+    fn format_negotiate() {
+        assert_eq!(Format::negotiate(None, None).unwrap(), Format::Json);
+        assert_eq!(Format::negotiate(Some("csv"), None).unwrap(), Format::Csv);
+        assert_eq!(
+            Format::negotiate(Some("json"), Some("text/csv")).unwrap(),
+            Format::Json
+        );
+        assert_eq!(
+            Format::negotiate(None, Some("text/html, text/csv;q=0.9")).unwrap(),
+            Format::Csv
+        );
+        assert_eq!(
+            Format::negotiate(None, Some("application/json")).unwrap(),
+            Format::Json
+        );
+        // q-values
+        assert_eq!(
+            Format::negotiate(None, Some("text/csv;q=0")).unwrap(),
+            Format::Json
+        );
+        assert_eq!(
+            Format::negotiate(None, Some("application/json, text/csv;q=0.1")).unwrap(),
+            Format::Json
+        );
+        assert_eq!(
+            Format::negotiate(None, Some("application/json;q=0.5, text/csv")).unwrap(),
+            Format::Csv
+        );
+        assert_eq!(
+            Format::negotiate(None, Some("text/*;q=0.9, */*;q=0.1")).unwrap(),
+            Format::Csv
+        );
+        assert_eq!(
+            Format::negotiate(None, Some("text/html, */*;q=0.8")).unwrap(),
+            Format::Json
+        );
+        assert!(matches!(
+            Format::negotiate(Some("xml"), None),
+            Err(Error::InvalidFormat(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn try_reply_csv_symbols_multiple_days() {
+        let file = File::open("seed_rates.xml").unwrap();
+        let envelope: Envelope = serde_xml_rs::from_reader(&file).unwrap();
+        let dates = envelope.cube.dates;
+        let days = dates.len();
+
+        let params = Params {
+            start_at: Some("2019-07-22".to_string()),
+            end_at: Some("2019-10-18".to_string()),
+            symbols: Some("USD,JPY".to_string()),
+            ..Default::default()
+        };
+        let response = try_reply(dates, params, Format::Csv)
+            .unwrap()
+            .into_response();
+        assert_eq!(
+            response.headers()["content-type"],
+            "text/csv; charset=utf-8"
+        );
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body_str = String::from_utf8(body.as_ref().to_vec()).unwrap();
+        let lines = body_str.lines().collect::<Vec<&str>>();
+        assert_eq!(lines.len(), days + 1);
+        assert_eq!(lines[0], "date,USD,JPY");
+        assert_eq!(lines[1], "2019-07-22,1.1215,121.03");
+        assert_eq!(lines[days], "2019-10-18,1.1144,120.97");
+    }
+
+    #[tokio::test]
+    async fn try_reply_order() {
+        let file = File::open("seed_rates.xml").unwrap();
+        let envelope: Envelope = serde_xml_rs::from_reader(&file).unwrap();
+        let dates = envelope.cube.dates;
+
+        let params = Params {
+            start_at: Some("2019-07-22".to_string()),
+            end_at: Some("2019-10-18".to_string()),
+            order: Some("USD,GBP".to_string()),
+            ..Default::default()
+        };
+        let response = build_response(dates.clone(), params).unwrap();
+        let rates = response["rates"].as_object().unwrap();
+        let days = rates.keys().cloned().collect::<Vec<String>>();
+        let mut sorted_days = days.clone();
+        sorted_days.sort();
+        assert_eq!(days, sorted_days);
+        for currencies in rates.values() {
+            let names = currencies
+                .as_object()
+                .unwrap()
+                .keys()
+                .map(String::as_str)
+                .collect::<Vec<&str>>();
+            assert_eq!(&names[..3], &["USD", "GBP", "AUD"]);
+            let mut sorted_names = names[2..].to_vec();
+            sorted_names.sort_unstable();
+            assert_eq!(&names[2..], &sorted_names[..]);
+        }
+
+        // identical queries produce identical bodies
+        let bodies = (0..2)
+            .map(|_| {
+                let params = Params {
+                    start_at: Some("2019-07-22".to_string()),
+                    end_at: Some("2019-10-18".to_string()),
+                    ..Default::default()
+                };
+                build_response(dates.clone(), params).unwrap().to_string()
+            })
+            .collect::<Vec<String>>();
+        assert_eq!(bodies[0], bodies[1]);
+    }
+
+    #[test]
+    fn rounding_from_params() {
+        let rounding = Rounding::from_params(None, None).unwrap();
+        assert_eq!(rounding.precision, None);
+        assert_eq!(
+            rounding
+                .apply(Decimal::from_str("1.2300").unwrap())
+                .to_string(),
+            "1.23"
+        );
+
+        let rounding = Rounding::from_params(Some("2"), None).unwrap();
+        assert_eq!(
+            rounding
+                .apply(Decimal::from_str("1.225").unwrap())
+                .to_string(),
+            "1.22"
+        );
+        let rounding = Rounding::from_params(Some("2"), Some("half_up")).unwrap();
+        assert_eq!(
+            rounding
+                .apply(Decimal::from_str("1.225").unwrap())
+                .to_string(),
+            "1.23"
+        );
+        let rounding = Rounding::from_params(Some("2"), Some("floor")).unwrap();
+        assert_eq!(
+            rounding
+                .apply(Decimal::from_str("-1.221").unwrap())
+                .to_string(),
+            "-1.23"
+        );
+
+        assert!(matches!(
+            Rounding::from_params(Some("29"), None),
+            Err(Error::InvalidPrecision(_))
+        ));
+        assert!(matches!(
+            Rounding::from_params(Some("2"), Some("nearest")),
+            Err(Error::InvalidRounding(_))
+        ));
+        assert!(matches!(
+            Rounding::from_params(None, Some("up")),
+            Err(Error::MissingParameter("precision"))
+        ));
+    }
+
+    #[tokio::test]
+    async fn try_reply_exact_decimals() {
+        let file = File::open("seed_rates.xml").unwrap();
+        let envelope: Envelope = serde_xml_rs::from_reader(&file).unwrap();
+        let date = envelope.cube.dates.into_iter().next().unwrap();
+
+        let params = Params {
+            base: Some("GBP".to_string()),
+            symbols: Some("USD,IDR".to_string()),
+            precision: Some("4".to_string()),
+            rounding: Some("down".to_string()),
+            ..Default::default()
+        };
+        let response = try_reply(vec![date.clone()], params, Format::Json)
+            .unwrap()
+            .into_response();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(
+            body.as_ref(),
+            br#"{"rates":{"USD":1.2892,"IDR":18248.5682},"base":"GBP","date":"2019-10-18"}"#
+        );
+
+        // rates are served exactly as published
+        let response = try_reply(vec![date], Params::default(), Format::Json)
+            .unwrap()
+            .into_response();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body_str = String::from_utf8(body.as_ref().to_vec()).unwrap();
+        assert!(body_str.contains(r#""IDR":15773.15,"#));
+        assert!(body_str.contains(r#""USD":1.1144,"#));
+    }
+
+    #[tokio::test]
+    async fn grouped_reply_monthly() {
+        let file = File::open("seed_rates.xml").unwrap();
+        let envelope: Envelope = serde_xml_rs::from_reader(&file).unwrap();
+        let dates = envelope.cube.dates;
+
+        let params = Params {
+            start_at: Some("2019-07-22".to_string()),
+            end_at: Some("2019-10-18".to_string()),
+            symbols: Some("USD".to_string()),
+            ..Default::default()
+        };
+        let response = grouped_reply(
+            dates.clone(),
+            params,
+            Format::Json,
+            Group::Month,
+            Aggregation::Last,
+        )
+        .unwrap()
+        .into_response();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let periods = body["rates"].as_array().unwrap();
+        assert_eq!(periods.len(), 4);
+        assert_eq!(periods[0]["start"], "2019-07-22");
+        assert_eq!(periods[0]["end"], "2019-07-31");
+        assert_eq!(periods[3]["start"], "2019-10-01");
+        assert_eq!(periods[3]["end"], "2019-10-18");
+        assert_eq!(
+            periods[3]["rates"]["USD"],
+            decimal_json(Decimal::new(11144, 4))
+        );
+        assert_eq!(body["group"], "month");
+        assert_eq!(body["agg"], "last");
+
+        let params = Params {
+            start_at: Some("2019-07-22".to_string()),
+            end_at: Some("2019-10-18".to_string()),
+            symbols: Some("USD,GBP".to_string()),
+            precision: Some("2".to_string()),
+            ..Default::default()
+        };
+        let response = grouped_reply(dates, params, Format::Csv, Group::Quarter, Aggregation::Max)
+            .unwrap()
+            .into_response();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body_str = String::from_utf8(body.to_vec()).unwrap();
+        let mut lines = body_str.lines();
+        assert_eq!(lines.next(), Some("start,end,USD,GBP"));
+        assert!(lines.next().unwrap().starts_with("2019-07-22,2019-09-30,"));
+        assert!(lines.next().unwrap().starts_with("2019-10-01,2019-10-18,"));
+        assert_eq!(lines.next(), None);
+    }
+
+    #[tokio::test]
+    async fn fluctuation_reply() {
+        let file = File::open("seed_rates.xml").unwrap();
+        let envelope: Envelope = serde_xml_rs::from_reader(&file).unwrap();
+        let mut dates = envelope.cube.dates;
+        let end = dates.remove(0);
+        let start = dates.pop().unwrap();
+
+        let params = Params {
+            symbols: Some("USD,GBP".to_string()),
+            precision: Some("4".to_string()),
+            ..Default::default()
+        };
+        let response = super::fluctuation_reply(start.clone(), end.clone(), params, Format::Json)
+            .unwrap()
+            .into_response();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(
+            body.as_ref(),
+            br#"{"rates":{"USD":{"start_rate":1.1215,"end_rate":1.1144,"change":-0.0071,"change_pct":-0.6331},"GBP":{"start_rate":0.8997,"end_rate":0.8644,"change":-0.0353,"change_pct":-3.9270}},"base":"EUR","start_at":"2019-07-22","end_at":"2019-10-18"}"#
+        );
+
+        let params = Params {
+            base: Some("USD".to_string()),
+            symbols: Some("USD".to_string()),
+            ..Default::default()
+        };
+        let response = super::fluctuation_reply(start, end, params, Format::Csv)
+            .unwrap()
+            .into_response();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(
+            body.as_ref(),
+            b"currency,start_rate,end_rate,change,change_pct\nUSD,1,1,0,0\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn stats_reply() {
+        let file = File::open("seed_rates.xml").unwrap();
+        let envelope: Envelope = serde_xml_rs::from_reader(&file).unwrap();
+        let dates = envelope.cube.dates;
+
+        let params = Params {
+            symbols: Some("USD,GBP".to_string()),
+            precision: Some("4".to_string()),
+            ..Default::default()
+        };
+        let response = super::stats_reply(dates.clone(), params, Format::Json)
+            .unwrap()
+            .into_response();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["start_at"], "2019-07-22");
+        assert_eq!(body["end_at"], "2019-10-18");
+        let usd = &body["rates"]["USD"];
+        assert_eq!(usd["days"], dates.len());
+        assert!(usd["min"].as_f64().unwrap() <= usd["median"].as_f64().unwrap());
+        assert!(usd["median"].as_f64().unwrap() <= usd["max"].as_f64().unwrap());
+        assert!(usd["std_dev"].as_f64().unwrap() > 0.0);
+        assert!(usd["volatility"].as_f64().unwrap() > 0.0);
+        let names = body["rates"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect::<Vec<&str>>();
+        assert_eq!(names, ["USD", "GBP"]);
+
+        let params = Params {
+            symbols: Some("USD".to_string()),
+            base: Some("USD".to_string()),
+            ..Default::default()
+        };
+        let response = super::stats_reply(dates, params, Format::Csv)
+            .unwrap()
+            .into_response();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body_str = String::from_utf8(body.to_vec()).unwrap();
+        let mut lines = body_str.lines();
+        assert_eq!(
+            lines.next(),
+            Some("currency,days,min,min_date,max,max_date,mean,median,std_dev,volatility")
+        );
+        assert_eq!(
+            lines.next(),
+            Some("USD,65,1,2019-07-22,1,2019-07-22,1,1,0,0")
+        );
+    }
+
+    #[tokio::test]
+    async fn currencies_reply() {
+        let spans = [
+            ("HRK", "1999-01-04", "2022-12-30"),
+            ("USD", "1999-01-04", "2023-01-02"),
+            ("XYZ", "2023-01-02", "2023-01-02"),
+        ]
+        .iter()
+        .map(|(code, first, last)| (code.to_string(), (first.to_string(), last.to_string())))
+        .collect::<BTreeMap<String, (String, String)>>();
+
+        let response = super::currencies_reply(spans.clone(), "2023-01-02", Format::Json)
+            .unwrap()
+            .into_response();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["date"], "2023-01-02");
+        let currencies = &body["currencies"];
+        assert_eq!(
+            currencies["HRK"].to_string(),
+            r#"{"name":"Kuna","numeric":"191","minor_units":2,"symbol":"kn","first_date":"1999-01-04","last_date":"2022-12-30","active":false,"fixed_rate":7.53450,"fixed_since":"2023-01-01"}"#
+        );
+        assert_eq!(
+            currencies["USD"].to_string(),
+            r#"{"name":"US Dollar","numeric":"840","minor_units":2,"symbol":"$","first_date":"1999-01-04","last_date":"2023-01-02","active":true,"fixed_rate":null,"fixed_since":null}"#
+        );
+        assert_eq!(
+            currencies["XYZ"].to_string(),
+            r#"{"name":null,"numeric":null,"minor_units":null,"symbol":null,"first_date":"2023-01-02","last_date":"2023-01-02","active":true,"fixed_rate":null,"fixed_since":null}"#
+        );
+        // legacy currencies are listed even if they were never published
+        assert_eq!(
+            currencies["DEM"].to_string(),
+            r#"{"name":"Deutsche Mark","numeric":"276","minor_units":2,"symbol":"DM","first_date":null,"last_date":null,"active":false,"fixed_rate":1.95583,"fixed_since":"1999-01-01"}"#
+        );
+        assert_eq!(
+            currencies.as_object().unwrap().len(),
+            3 + legacy::CURRENCIES.len() - 1
+        );
+
+        let response = super::currencies_reply(spans, "2023-01-02", Format::Csv)
+            .unwrap()
+            .into_response();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body_str = String::from_utf8(body.to_vec()).unwrap();
+        let lines = body_str.lines().collect::<Vec<&str>>();
+        assert_eq!(
+            lines[0],
+            "code,name,numeric,minor_units,symbol,first_date,last_date,active,fixed_rate,fixed_since"
+        );
+        assert!(lines.contains(&"HRK,Kuna,191,2,kn,1999-01-04,2022-12-30,false,7.53450,2023-01-01"));
+        assert!(lines.contains(&"USD,US Dollar,840,2,$,1999-01-04,2023-01-02,true,,"));
+        assert!(lines.contains(&"XYZ,,,,,2023-01-02,2023-01-02,true,,"));
+    }
+
+    // RUB and HRK are no longer published after 2022
+    fn discontinued_dates() -> Vec<Date> {
+        [
+            (
+                "2022-12-29",
+                &[("USD", "1.0639"), ("RUB", "75.0"), ("HRK", "7.5365")][..],
+            ),
+            (
+                "2022-12-30",
+                &[("USD", "1.0666"), ("RUB", "76.5"), ("HRK", "7.5365")][..],
+            ),
+            ("2023-01-02", &[("USD", "1.0683")][..]),
+            ("2023-01-03", &[("USD", "1.0545")][..]),
+        ]
+        .iter()
+        .map(|(day, currencies)| Date {
+            value: day.to_string(),
+            currencies: currencies
+                .iter()
+                .map(|(name, rate)| Currency {
+                    name: name.to_string(),
+                    rate: Decimal::from_str(rate).unwrap(),
+                })
+                .collect(),
+        })
+        .collect()
+    }
+
+    #[test]
+    fn rebase_missing_rates() {
+        let params = Params {
+            start_at: Some("2022-12-29".to_string()),
+            end_at: Some("2023-01-03".to_string()),
+            symbols: Some("USD,RUB".to_string()),
+            ..Default::default()
+        };
+        let response = build_response(discontinued_dates(), params).unwrap();
+        assert_eq!(
+            response["rates"]["2023-01-02"],
+            json!({"USD": decimal_json(Decimal::new(10683, 4)), "RUB": null})
+        );
+        assert_eq!(
+            response["gaps"],
+            json!({"RUB": [{"start_at": "2023-01-02", "end_at": "2023-01-03"}]})
+        );
+
+        // rates can't be rebased on dates without the base
+        let params = Params {
+            base: Some("RUB".to_string()),
+            ..Default::default()
+        };
+        let rates = rebase(discontinued_dates(), &params, Rounding::exact()).unwrap();
+        assert_eq!(
+            rates["2023-01-03"],
+            vec![
+                ("HRK".to_string(), None),
+                ("RUB".to_string(), None),
+                ("USD".to_string(), None)
+            ]
+        );
+        assert!(rates["2022-12-30"].iter().all(|(_, rate)| rate.is_some()));
+
+        let params = Params {
+            symbols: Some("USD,RUB".to_string()),
+            strict: Some("true".to_string()),
+            ..Default::default()
+        };
+        let err = rebase(discontinued_dates(), &params, Rounding::exact()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "missing rates for RUB on 2023-01-02 to 2023-01-03"
+        );
+
+        let params = Params {
+            symbols: Some("USD".to_string()),
+            strict: Some("true".to_string()),
+            ..Default::default()
+        };
+        assert!(rebase(discontinued_dates(), &params, Rounding::exact()).is_ok());
+
+        let params = Params {
+            symbols: Some("USD,XYZ".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            rebase(discontinued_dates(), &params, Rounding::exact()),
+            Err(Error::InvalidSymbol)
+        ));
+    }
+
+    #[test]
+    fn rebase_legacy_currencies() {
+        let params = Params {
+            symbols: Some("DEM,HRK".to_string()),
+            ..Default::default()
+        };
+        let rates = rebase(discontinued_dates(), &params, Rounding::exact()).unwrap();
+        // HRK is published until it adopts the euro
+        assert_eq!(
+            rates["2022-12-30"],
+            vec![
+                ("DEM".to_string(), Some(Decimal::new(195583, 5))),
+                ("HRK".to_string(), Some(Decimal::new(75365, 4)))
+            ]
+        );
+        assert_eq!(
+            rates["2023-01-02"],
+            vec![
+                ("DEM".to_string(), Some(Decimal::new(195583, 5))),
+                ("HRK".to_string(), Some(Decimal::new(75345, 4)))
+            ]
+        );
+
+        let params = Params {
+            base: Some("DEM".to_string()),
+            symbols: Some("FRF,USD".to_string()),
+            precision: Some("6".to_string()),
+            ..Default::default()
+        };
+        let rates = rebase(discontinued_dates(), &params, Rounding::exact()).unwrap();
+        let rounding = Rounding::from_params(Some("6"), None).unwrap();
+        assert_eq!(
+            rates["2023-01-03"]
+                .iter()
+                .map(|(name, rate)| (name.as_str(), rounding.apply(rate.unwrap())))
+                .collect::<Vec<(&str, Decimal)>>(),
+            vec![
+                ("FRF", Decimal::new(3353855, 6)),
+                ("USD", Decimal::new(539157, 6))
+            ]
+        );
+
+        // not derived unless requested
+        let rates = rebase(discontinued_dates(), &Params::default(), Rounding::exact()).unwrap();
+        assert!(rates["2023-01-03"].iter().all(|(name, _)| name != "DEM"));
+    }
+
+    #[tokio::test]
+    async fn matrix_reply() {
+        let file = File::open("seed_rates.xml").unwrap();
+        let envelope: Envelope = serde_xml_rs::from_reader(&file).unwrap();
+        let date = envelope.cube.dates.into_iter().next().unwrap();
+
+        let params = MatrixParams {
+            symbols: Some("USD,GBP,USD".to_string()),
+            precision: Some("4".to_string()),
+            ..Default::default()
+        };
+        let response = super::matrix_reply(date.clone(), None, params, Format::Json)
+            .unwrap()
+            .into_response();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(
+            body.as_ref(),
+            br#"{"rates":{"USD":{"USD":1,"GBP":0.7756},"GBP":{"USD":1.2893,"GBP":1}},"date":"2019-10-18"}"#
+        );
+
+        let params = MatrixParams {
+            symbols: Some("DEM,USD".to_string()),
+            precision: Some("6".to_string()),
+            ..Default::default()
+        };
+        let response = super::matrix_reply(date.clone(), None, params, Format::Csv)
+            .unwrap()
+            .into_response();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(
+            body.as_ref(),
+            b"base,DEM,USD\nDEM,1,0.569784\nUSD,1.755052,1\n"
+        );
+
+        let params = MatrixParams {
+            symbols: Some("USD,XYZ".to_string()),
+            ..Default::default()
+        };
+        assert!(super::matrix_reply(date, None, params, Format::Json).is_err());
+    }
+
+    #[test]
+    fn parse_batch() {
+        let json = br#"[
+            {"id": 1, "date": "2019-10-18", "amount": 10.50, "from": "USD", "to": "GBP"},
+            {"id": "b", "amount": "3", "from": "EUR", "to": "JPY"}
+        ]"#;
+        let transactions = super::parse_batch(json, Some("application/json")).unwrap();
+        assert_eq!(
+            transactions,
+            vec![
+                Transaction {
+                    id: json!(1),
+                    date: Some("2019-10-18".to_string()),
+                    amount: "10.50".to_string(),
+                    from: "USD".to_string(),
+                    to: "GBP".to_string(),
+                },
+                Transaction {
+                    id: json!("b"),
+                    date: None,
+                    amount: "3".to_string(),
+                    from: "EUR".to_string(),
+                    to: "JPY".to_string(),
+                },
+            ]
+        );
+
+        let csv = b"id,date,amount,from,to\n1,2019-10-18,10.50,USD,GBP\nb,,3,EUR,JPY\n";
+        let transactions = super::parse_batch(csv, Some("text/csv; charset=utf-8")).unwrap();
+        assert_eq!(transactions[0].id, json!("1"));
+        assert_eq!(transactions[1].date, None);
+
+        assert!(matches!(
+            super::parse_batch(b"id,amount\n1,2\n", Some("text/csv")),
+            Err(Error::InvalidBatch(_))
+        ));
+        assert!(matches!(
+            super::parse_batch(b"{}", None),
+            Err(Error::InvalidBatch(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn convert_batch() {
+        let file = File::open("seed_rates.xml").unwrap();
+        let envelope: Envelope = serde_xml_rs::from_reader(&file).unwrap();
+        let dates = envelope
+            .cube
+            .dates
+            .into_iter()
+            .map(|date| (date.value_as_date().unwrap(), date))
+            .collect::<BTreeMap<NaiveDate, Date>>();
+        let current = dates.values().next_back().cloned();
+
+        let transaction =
+            |id: i32, date: Option<&str>, amount: &str, from: &str, to: &str| Transaction {
+                id: json!(id),
+                date: date.map(String::from),
+                amount: amount.to_string(),
+                from: from.to_string(),
+                to: to.to_string(),
+            };
+        let transactions = vec![
+            // saturday
+            transaction(1, Some("2019-10-19"), "100", "USD", "GBP"),
+            transaction(2, None, "100", "DEM", "USD"),
+            transaction(3, Some("2019-10-18"), "1e3", "USD", "GBP"),
+            transaction(4, Some("2019-10-18"), "100", "USD", "XYZ"),
+        ];
+        let rounding = Rounding::from_params(Some("2"), None).unwrap();
+        let conversions = transactions
+            .into_iter()
+            .map(|transaction| {
+                let conversion = convert_transaction(
+                    &transaction,
+                    &dates,
+                    current.as_ref(),
+                    Fallback::Previous,
+                    rounding,
+                );
+                (transaction, conversion)
+            })
+            .collect();
+
+        let response = batch_reply(conversions, Format::Csv)
+            .unwrap()
+            .into_response();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(
+            String::from_utf8(body.to_vec()).unwrap(),
+            "id,date,from,to,amount,rate,result,rate_date,error\n\
+             1,2019-10-19,USD,GBP,100,0.78,77.56,2019-10-18,\n\
+             2,,DEM,USD,100,0.57,56.98,2019-10-18,\n\
+             3,2019-10-18,USD,GBP,1e3,,,,`1e3` is an invalid amount\n\
+             4,2019-10-18,USD,XYZ,100,,,,symbol list contains invalid symbols\n"
+        );
+    }
+
+    // the api with the default settings over the seed rates
+    fn seeded_routes(
+        seeded: &mock_ecb::Seeded,
+    ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+        routes(
+            seeded.db.clone(),
+            Cors::default().filter().unwrap(),
+            Caching::default(),
+            Metrics::new(),
+        )
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn convert_route() {
+        let seeded = mock_ecb::seeded_db().await;
+        let routes = seeded_routes(&seeded);
+        let convert = |query: &str| {
+            warp::test::request()
+                .path(&format!("/api/v1/convert?{}", query))
+                .reply(&routes)
+        };
+
+        let response = convert("from=EUR&to=USD&amount=2").await;
+        assert_eq!(response.status(), 200);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["rate"], decimal_json(Decimal::new(11144, 4)));
+        assert_eq!(body["result"], decimal_json(Decimal::new(22288, 4)));
+        assert_eq!(body["date"], "2019-10-18");
+        assert_eq!(body.get("requested_date"), None);
+
+        // the date is the one the rates are from, a saturday falls back to the friday
+        let response = convert("from=EUR&to=USD&amount=2&date=2019-10-19&fallback=previous").await;
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["date"], "2019-10-18");
+        assert_eq!(body["requested_date"], "2019-10-19");
+
+        for (query, status, msg) in [
+            ("to=USD&amount=1", 400, "`from` parameter must be present"),
+            ("from=EUR&amount=1", 400, "`to` parameter must be present"),
+            ("from=EUR&to=USD", 400, "`amount` parameter must be present"),
+            (
+                "from=EUR&to=USD&amount=1e3",
+                400,
+                "`1e3` is an invalid amount",
+            ),
+            (
+                "from=EUR&to=USD&amount=79228162514264337593543950335",
+                400,
+                "`79228162514264337593543950335` is an invalid amount",
+            ),
+            (
+                "from=XYZ&to=USD&amount=1",
+                400,
+                "`XYZ` is an invalid base currency",
+            ),
+            (
+                "from=EUR&to=XYZ&amount=1",
+                400,
+                "symbol list contains invalid symbols",
+            ),
+            ("from=EUR&to=USD&amount=1&date=2019-10-19", 404, "Not Found"),
+        ] {
+            let response = convert(query).await;
+            assert_eq!(response.status(), status, "{}", query);
+            let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+            assert_eq!(body["msg"], msg, "{}", query);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn fluctuation_route() {
+        let seeded = mock_ecb::seeded_db().await;
+        let routes = seeded_routes(&seeded);
+        let fluctuation = |query: &str| {
+            warp::test::request()
+                .path(&format!("/api/v1/fluctuation?symbols=USD&{}", query))
+                .reply(&routes)
+        };
+
+        // weekend boundaries resolve to the first and last published dates within them
+        let response = fluctuation("start_at=2019-10-12&end_at=2019-10-20&precision=2").await;
+        assert_eq!(response.status(), 200);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["start_at"], "2019-10-14");
+        assert_eq!(body["end_at"], "2019-10-18");
+        let usd = &body["rates"]["USD"];
+        assert_eq!(usd["start_rate"], decimal_json(Decimal::new(110, 2)));
+        assert_eq!(usd["end_rate"], decimal_json(Decimal::new(111, 2)));
+        assert_eq!(usd["change"], decimal_json(Decimal::new(1, 2)));
+        assert_eq!(usd["change_pct"], decimal_json(Decimal::new(102, 2)));
+
+        // a single published date within the boundaries
+        let response = fluctuation("start_at=2019-10-18&end_at=2019-10-20").await;
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["start_at"], "2019-10-18");
+        assert_eq!(body["end_at"], "2019-10-18");
+        let usd = &body["rates"]["USD"];
+        assert_eq!(usd["start_rate"], usd["end_rate"]);
+        assert_eq!(usd["change"], decimal_json(Decimal::ZERO));
+
+        // no published date within the boundaries, start_at resolves after end_at
+        let response = fluctuation("start_at=2019-10-12&end_at=2019-10-13").await;
+        assert_eq!(response.status(), 404);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn convert_batch_route() {
+        let seeded = mock_ecb::seeded_db().await;
+        let routes = seeded_routes(&seeded);
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/api/v1/convert/batch?fallback=next")
+            .header("content-type", "text/csv")
+            .body("id,date,amount,from,to\na,2019-07-20,2,EUR,USD\nb,2019-10-18,1,GBP,EUR\n")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 200);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        let results = body["results"].as_array().unwrap();
+        assert_eq!(results[0]["rate_date"], "2019-07-22");
+        assert_eq!(results[0]["result"], decimal_json(Decimal::new(2243, 3)));
+        assert_eq!(results[1]["rate_date"], "2019-10-18");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn day_fallback() {
+        let seeded = mock_ecb::seeded_db().await;
+        let routes = seeded_routes(&seeded);
+
+        // a saturday
+        let response = warp::test::request()
+            .path("/api/v1/2019-10-19?fallback=previous&symbols=USD")
+            .reply(&routes)
+            .await;
+        let json: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(json["date"], "2019-10-18");
+        assert_eq!(json["requested_date"], "2019-10-19");
+
+        let response = warp::test::request()
+            .path("/api/v1/2019-10-19?fallback=previous&symbols=USD&format=csv")
+            .reply(&routes)
+            .await;
+        assert_eq!(
+            response.body(),
+            "date,requested_date,USD\n2019-10-18,2019-10-19,1.1144\n"
+        );
+
+        let response = warp::test::request()
+            .path("/api/v1/2019-10-18?symbols=USD&format=csv")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.body(), "date,USD\n2019-10-18,1.1144\n");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn caching_headers() {
+        let seeded = mock_ecb::seeded_db().await;
+        let routes = seeded_routes(&seeded);
+
+        let response = warp::test::request()
+            .path("/api/v1/latest")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()["vary"], "accept");
+        let etag = response.headers()["etag"].clone();
+        assert_eq!(
+            response.headers()["last-modified"],
+            "Fri, 18 Oct 2019 15:00:00 GMT"
+        );
+        assert!(!response.headers()["cache-control"]
+            .to_str()
+            .unwrap()
+            .contains("immutable"));
+
+        let response = warp::test::request()
+            .path("/api/v1/latest")
+            .header("if-none-match", etag.clone())
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 304);
+        assert!(response.body().is_empty());
+        assert_eq!(response.headers()["vary"], "accept");
+
+        // another representation of the same rates, that doesn't depend on the Accept header
+        let response = warp::test::request()
+            .path("/api/v1/latest?format=csv")
+            .header("if-none-match", etag)
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()["vary"], "origin");
+
+        let response = warp::test::request()
+            .path("/api/v1/history?start_at=2019-07-22&end_at=2019-07-26")
+            .reply(&routes)
+            .await;
+        assert_eq!(
+            response.headers()["cache-control"],
+            "public, max-age=31536000, immutable"
+        );
+
+        let response = warp::test::request()
+            .path("/api/v1/2019-07-22")
+            .header("if-modified-since", "Mon, 22 Jul 2019 15:00:00 GMT")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 304);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn cors_preflight() {
+        let seeded = mock_ecb::seeded_db().await;
+        let routes = seeded_routes(&seeded);
+
+        let preflight = |path: &str| {
+            warp::test::request()
+                .method("OPTIONS")
+                .path(path)
+                .header("origin", "https://example.com")
+                .header("access-control-request-method", "POST")
+                .header("access-control-request-headers", "content-type")
+        };
+        let vary = |response: &warp::http::Response<Bytes>| {
+            response
+                .headers()
+                .get_all("vary")
+                .iter()
+                .map(|value| value.to_str().unwrap().to_string())
+                .collect::<Vec<String>>()
+        };
+        let response = preflight("/api/v1/convert/batch").reply(&routes).await;
+        assert_eq!(response.status(), 200);
+        assert!(response
+            .headers()
+            .contains_key("access-control-allow-methods"));
+        assert_eq!(vary(&response), ["origin"]);
+
+        // left to the other routes
+        let response = preflight("/healthz").reply(&routes).await;
+        assert_eq!(response.status(), 404);
+
+        let response = warp::test::request()
+            .path("/api/v1/latest")
+            .header("origin", "https://example.com")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 200);
+        assert!(response
+            .headers()
+            .contains_key("access-control-allow-origin"));
+        assert_eq!(vary(&response), ["accept", "origin"]);
+
+        // cached without an Origin, the response must not be served to cross-origin requests
+        let response = warp::test::request()
+            .path("/api/v1/latest?format=json")
+            .reply(&routes)
+            .await;
+        assert!(!response
+            .headers()
+            .contains_key("access-control-allow-origin"));
+        assert_eq!(vary(&response), ["origin"]);
+
+        // error bodies have to be readable cross-origin too
+        for (path, status) in [
+            ("/api/v1/latest?symbols=XYZ&format=json", 400),
+            ("/api/v1/2019-10-19?format=json", 404),
+        ] {
+            let response = warp::test::request()
+                .path(path)
+                .header("origin", "https://example.com")
+                .reply(&routes)
+                .await;
+            assert_eq!(response.status(), status);
+            assert_eq!(
+                response.headers()["access-control-allow-origin"],
+                "https://example.com"
+            );
+            assert_eq!(vary(&response), ["origin"]);
+            let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+            assert_eq!(body["code"], status);
+        }
+    }
 }
@@ -0,0 +1,306 @@
+// http caching of the responses built from stored rates, rates of a published date never change so
+// responses that only depend on past dates are cached for good, and the others until the next ECB
+// publication
+use chrono::{DateTime, Datelike, NaiveDate, TimeZone, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use warp::http::{header, HeaderValue, StatusCode};
+use warp::reply::Response;
+use warp::{Filter, Rejection, Reply};
+
+use crate::fetcher::Date;
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Caching {
+    // hour, in UTC, the reference rates are published at on working days
+    pub publication_hour: u32,
+    // seconds responses whose rates are overdue are cached for, e.g. while the updater catches up
+    // with a publication
+    pub min_max_age: u32,
+    // seconds responses that never change are cached for
+    pub immutable_max_age: u32,
+}
+
+impl Default for Caching {
+    fn default() -> Caching {
+        Caching {
+            // the ECB publishes the reference rates around 16:00 CET
+            publication_hour: 15,
+            min_max_age: 60,
+            // a year, the longest max-age allowed
+            immutable_max_age: 365 * 24 * 60 * 60,
+        }
+    }
+}
+
+// conditional request headers
+#[derive(Debug, Default)]
+pub struct Conditions {
+    pub if_none_match: Option<String>,
+    pub if_modified_since: Option<String>,
+}
+
+pub fn conditions() -> impl Filter<Extract = (Conditions,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("if-none-match")
+        .and(warp::header::optional::<String>("if-modified-since"))
+        .map(|if_none_match, if_modified_since| Conditions {
+            if_none_match,
+            if_modified_since,
+        })
+}
+
+// validators and lifetime of a response
+#[derive(Debug, PartialEq)]
+pub struct Freshness {
+    etag: String,
+    last_modified: DateTime<Utc>,
+    // seconds the response can be cached for
+    max_age: i64,
+    immutable: bool,
+}
+
+impl Freshness {
+    // `dates` are the rates the response is built from and `variant` anything else its body
+    // depends on, like the request params. The rates of the days before `current`, the newest
+    // stored date, are final so the response never changes if `last_day` requested is one of them.
+    // `None` when there are no rates to build the response from.
+    pub fn new<V: Serialize>(
+        dates: &[Date],
+        variant: &V,
+        last_day: NaiveDate,
+        current: NaiveDate,
+        now: DateTime<Utc>,
+        caching: &Caching,
+    ) -> Option<Freshness> {
+        let newest = dates.iter().map(|date| &date.value).max()?;
+        let newest = NaiveDate::parse_from_str(newest, "%Y-%m-%d").ok()?;
+
+        // strong validator, a hash of the rates and the variant that stays the same across builds
+        // and replicas. Rates are serialized as strings, so the scale is kept.
+        let mut hasher = Sha256::new();
+        hasher.update(bincode::serialize(dates).ok()?);
+        hasher.update(bincode::serialize(variant).ok()?);
+        let etag = hasher.finalize()[..16]
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>();
+
+        let immutable = last_day < current;
+        let max_age = if immutable {
+            i64::from(caching.immutable_max_age)
+        } else {
+            let expires = caching.publication(next_working_day(current)) - now;
+            expires.num_seconds().max(i64::from(caching.min_max_age))
+        };
+
+        Some(Freshness {
+            etag: format!("\"{}\"", etag),
+            last_modified: caching.publication(newest),
+            max_age,
+            immutable,
+        })
+    }
+
+    // reply 304 Not Modified if the request conditions match, otherwise the response `build`
+    // returns, along with the caching headers
+    pub fn reply<F>(&self, conditions: &Conditions, build: F) -> Result<Response, Rejection>
+    where
+        F: FnOnce() -> Result<Response, Rejection>,
+    {
+        let mut response = if self.not_modified(conditions) {
+            StatusCode::NOT_MODIFIED.into_response()
+        } else {
+            build()?
+        };
+
+        let cache_control = if self.immutable {
+            format!("public, max-age={}, immutable", self.max_age)
+        } else {
+            format!("public, max-age={}", self.max_age)
+        };
+        let last_modified = self
+            .last_modified
+            .format("%a, %d %b %Y %H:%M:%S GMT")
+            .to_string();
+        let headers = response.headers_mut();
+        for (name, value) in [
+            (header::ETAG, &self.etag),
+            (header::LAST_MODIFIED, &last_modified),
+            (header::CACHE_CONTROL, &cache_control),
+        ] {
+            if let Ok(value) = HeaderValue::from_str(value) {
+                headers.insert(name, value);
+            }
+        }
+        Ok(response)
+    }
+
+    fn not_modified(&self, conditions: &Conditions) -> bool {
+        // If-Modified-Since is ignored when If-None-Match is present, RFC 7232 section 3.3
+        if let Some(ref if_none_match) = conditions.if_none_match {
+            return if_none_match
+                .split(',')
+                .map(str::trim)
+                .any(|etag| etag == "*" || etag.trim_start_matches("W/") == self.etag);
+        }
+
+        conditions
+            .if_modified_since
+            .as_deref()
+            .and_then(|since| DateTime::parse_from_rfc2822(since).ok())
+            .is_some_and(|since| self.last_modified <= since)
+    }
+}
+
+impl Caching {
+    // time the rates of `day` are published at
+    fn publication(&self, day: NaiveDate) -> DateTime<Utc> {
+        Utc.from_utc_datetime(&day.and_hms(self.publication_hour, 0, 0))
+    }
+}
+
+// TARGET closing days other than weekends are not accounted for, responses just expire earlier
+fn next_working_day(day: NaiveDate) -> NaiveDate {
+    let mut next = day.succ();
+    while matches!(next.weekday(), Weekday::Sat | Weekday::Sun) {
+        next = next.succ();
+    }
+    next
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetcher::Currency;
+    use rust_decimal::Decimal;
+
+    fn date(value: &str, rate: Decimal) -> Date {
+        Date {
+            value: value.to_string(),
+            currencies: vec![Currency {
+                name: "USD".to_string(),
+                rate,
+            }],
+        }
+    }
+
+    fn day(day: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(day, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn freshness() {
+        let dates = vec![date("2019-10-18", Decimal::new(11, 1))];
+        let now = Utc.ymd(2019, 10, 19).and_hms(12, 0, 0);
+        let current = day("2019-10-18");
+        let caching = Caching::default();
+
+        // published on a friday, next publication on monday
+        let latest = Freshness::new(&dates, &"latest", current, current, now, &caching).unwrap();
+        assert_eq!(latest.max_age, 2 * 24 * 60 * 60 + 3 * 60 * 60);
+        assert!(!latest.immutable);
+        assert_eq!(
+            latest.last_modified,
+            Utc.ymd(2019, 10, 18).and_hms(15, 0, 0)
+        );
+
+        // overdue publication
+        let late = Utc.ymd(2019, 10, 21).and_hms(16, 0, 0);
+        let freshness =
+            Freshness::new(&dates, &"latest", current, current, late, &caching).unwrap();
+        assert_eq!(freshness.max_age, 60);
+
+        let past =
+            Freshness::new(&dates, &"latest", day("2019-10-17"), current, now, &caching).unwrap();
+        assert_eq!(past.max_age, 365 * 24 * 60 * 60);
+        assert!(past.immutable);
+
+        let caching = Caching {
+            publication_hour: 14,
+            min_max_age: 300,
+            immutable_max_age: 24 * 60 * 60,
+        };
+        let freshness = Freshness::new(&dates, &"latest", current, current, now, &caching).unwrap();
+        assert_eq!(freshness.max_age, 2 * 24 * 60 * 60 + 2 * 60 * 60);
+        let freshness =
+            Freshness::new(&dates, &"latest", current, current, late, &caching).unwrap();
+        assert_eq!(freshness.max_age, 300);
+        let past =
+            Freshness::new(&dates, &"latest", day("2019-10-17"), current, now, &caching).unwrap();
+        assert_eq!(past.max_age, 24 * 60 * 60);
+        let caching = Caching::default();
+
+        // the etag depends on the exact rates and the variant
+        // sha-256 of the bincode rates and variant, the same on every build
+        assert_eq!(latest.etag, "\"2c2f5d9714edc425c6e0e332bfac1f54\"");
+        let variant = Freshness::new(&dates, &"day", current, current, now, &caching).unwrap();
+        assert_ne!(latest.etag, variant.etag);
+        let scale = vec![date("2019-10-18", Decimal::new(110, 2))];
+        let scale = Freshness::new(&scale, &"latest", current, current, now, &caching).unwrap();
+        assert_ne!(latest.etag, scale.etag);
+
+        assert_eq!(
+            Freshness::new(&[], &"latest", current, current, now, &caching),
+            None
+        );
+    }
+
+    #[test]
+    fn reply_not_modified() {
+        let dates = vec![date("2019-10-17", Decimal::ONE)];
+        let now = Utc.ymd(2019, 10, 19).and_hms(12, 0, 0);
+        let caching = Caching::default();
+        let freshness = Freshness::new(
+            &dates,
+            &(),
+            day("2019-10-17"),
+            day("2019-10-18"),
+            now,
+            &caching,
+        )
+        .unwrap();
+        let build = || Ok(warp::reply::json(&()).into_response());
+
+        let response = freshness.reply(&Conditions::default(), build).unwrap();
+        assert_eq!(response.status(), 200);
+        let etag = response.headers()[header::ETAG]
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert_eq!(
+            response.headers()[header::LAST_MODIFIED],
+            "Thu, 17 Oct 2019 15:00:00 GMT"
+        );
+        assert_eq!(
+            response.headers()[header::CACHE_CONTROL],
+            "public, max-age=31536000, immutable"
+        );
+
+        let conditions = Conditions {
+            if_none_match: Some(format!("\"other\", W/{}", etag)),
+            if_modified_since: None,
+        };
+        let response = freshness.reply(&conditions, build).unwrap();
+        assert_eq!(response.status(), 304);
+        assert_eq!(response.headers()[header::ETAG], etag.as_str());
+
+        // If-None-Match wins over If-Modified-Since
+        let conditions = Conditions {
+            if_none_match: Some("\"other\"".to_string()),
+            if_modified_since: Some("Fri, 18 Oct 2019 15:00:00 GMT".to_string()),
+        };
+        assert_eq!(freshness.reply(&conditions, build).unwrap().status(), 200);
+
+        let conditions = Conditions {
+            if_none_match: None,
+            if_modified_since: Some("Fri, 18 Oct 2019 15:00:00 GMT".to_string()),
+        };
+        assert_eq!(freshness.reply(&conditions, build).unwrap().status(), 304);
+        let conditions = Conditions {
+            if_none_match: None,
+            if_modified_since: Some("Wed, 16 Oct 2019 15:00:00 GMT".to_string()),
+        };
+        assert_eq!(freshness.reply(&conditions, build).unwrap().status(), 200);
+    }
+}
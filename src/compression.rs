@@ -0,0 +1,363 @@
+// response compression negotiated with the `Accept-Encoding` header, responses smaller than
+// `min_size` are sent as is as compressing them saves little, and the compressed bodies of
+// immutable responses are kept in memory as they are requested over and over
+use std::collections::{HashMap, VecDeque};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use warp::http::header::{self, HeaderValue};
+use warp::http::StatusCode;
+use warp::hyper::body::{self, Bytes};
+use warp::hyper::Body;
+use warp::reply::Response;
+use warp::{Filter, Rejection, Reply};
+
+use crate::error::Error;
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Encoding {
+    Brotli,
+    Zstd,
+    Gzip,
+}
+
+impl Encoding {
+    // in order of preference when the client accepts several equally
+    const ALL: [Encoding; 3] = [Encoding::Brotli, Encoding::Zstd, Encoding::Gzip];
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Zstd => "zstd",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    // the supported encoding with the highest quality in the `Accept-Encoding` header
+    pub fn negotiate(accept_encoding: &str) -> Option<Encoding> {
+        let accepted = accept_encoding
+            .split(',')
+            .filter_map(|coding| {
+                let mut params = coding.split(';');
+                let name = params.next()?.trim().to_ascii_lowercase();
+                let quality = params
+                    .find_map(|param| param.trim().strip_prefix("q="))
+                    .map_or(1.0, |q| q.trim().parse::<f32>().unwrap_or(0.0));
+                Some((name, quality))
+            })
+            .collect::<Vec<(String, f32)>>();
+        let quality = |name: &str| {
+            accepted
+                .iter()
+                .find(|(coding, _)| coding == name)
+                .or_else(|| accepted.iter().find(|(coding, _)| coding == "*"))
+                .map_or(0.0, |(_, quality)| *quality)
+        };
+
+        let mut best: Option<(Encoding, f32)> = None;
+        for encoding in Encoding::ALL {
+            let quality = quality(encoding.as_str());
+            if quality > 0.0 && best.is_none_or(|(_, best)| quality > best) {
+                best = Some((encoding, quality));
+            }
+        }
+        best.map(|(encoding, _)| encoding)
+    }
+
+    fn encode(&self, body: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            Encoding::Brotli => {
+                let mut encoder = brotli::CompressorWriter::new(Vec::new(), 4096, 5, 22);
+                encoder.write_all(body)?;
+                Ok(encoder.into_inner())
+            }
+            Encoding::Zstd => zstd::encode_all(body, 3),
+            Encoding::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(body)?;
+                encoder.finish()
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Compression {
+    // smallest body compressed, in bytes
+    pub min_size: usize,
+    // memory for the compressed bodies of immutable responses, in bytes
+    pub cache_size: usize,
+}
+
+impl Default for Compression {
+    fn default() -> Compression {
+        Compression {
+            min_size: 1024,
+            cache_size: 64 * 1024 * 1024,
+        }
+    }
+}
+
+// compressed bodies by the strong `ETag` of the response they come from, the oldest are evicted
+// first once the cache is full
+struct Cache {
+    max_size: usize,
+    size: usize,
+    bodies: HashMap<(String, Encoding), Bytes>,
+    order: VecDeque<(String, Encoding)>,
+}
+
+impl Cache {
+    fn get(&self, etag: &str, encoding: Encoding) -> Option<Bytes> {
+        self.bodies.get(&(etag.to_string(), encoding)).cloned()
+    }
+
+    fn insert(&mut self, etag: &str, encoding: Encoding, body: Bytes) {
+        let key = (etag.to_string(), encoding);
+        if body.len() > self.max_size || self.bodies.contains_key(&key) {
+            return;
+        }
+        while self.size + body.len() > self.max_size {
+            match self.order.pop_front() {
+                Some(key) => {
+                    self.size -= self.bodies.remove(&key).map_or(0, |body| body.len());
+                }
+                None => break,
+            }
+        }
+
+        self.size += body.len();
+        self.bodies.insert(key.clone(), body);
+        self.order.push_back(key);
+    }
+}
+
+// compress the replies of `filter`
+pub fn wrap<F, R>(
+    filter: F,
+    compression: Compression,
+) -> impl Filter<Extract = (Response,), Error = Rejection> + Clone
+where
+    F: Filter<Extract = (R,), Error = Rejection> + Clone + Send + Sync + 'static,
+    R: Reply,
+{
+    let cache = Arc::new(Mutex::new(Cache {
+        max_size: compression.cache_size,
+        size: 0,
+        bodies: HashMap::new(),
+        order: VecDeque::new(),
+    }));
+
+    warp::header::optional::<String>("accept-encoding")
+        .and(filter)
+        .and_then(move |accept_encoding: Option<String>, reply: R| {
+            let cache = cache.clone();
+            let min_size = compression.min_size;
+            async move {
+                let encoding = accept_encoding.as_deref().and_then(Encoding::negotiate);
+                compress(reply.into_response(), encoding, min_size, &cache)
+                    .await
+                    .map_err(warp::reject::custom)
+            }
+        })
+}
+
+async fn compress(
+    response: Response,
+    encoding: Option<Encoding>,
+    min_size: usize,
+    cache: &Mutex<Cache>,
+) -> Result<Response, Error> {
+    let compressible = response.status() == StatusCode::OK
+        && !response.headers().contains_key(header::CONTENT_ENCODING)
+        && response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| {
+                value.starts_with("text/") || value.starts_with("application/json")
+            });
+    if !compressible {
+        return Ok(response);
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let body = body::to_bytes(body)
+        .await
+        .map_err(|err| Error::Compression(err.to_string()))?;
+    if body.len() < min_size {
+        return Ok(Response::from_parts(parts, Body::from(body)));
+    }
+
+    // the representation depends on the request's Accept-Encoding from now on
+    parts
+        .headers
+        .append(header::VARY, HeaderValue::from_static("accept-encoding"));
+    let encoding = match encoding {
+        Some(encoding) => encoding,
+        None => return Ok(Response::from_parts(parts, Body::from(body))),
+    };
+
+    let immutable = parts
+        .headers
+        .get(header::CACHE_CONTROL)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.contains("immutable"));
+    let etag = parts
+        .headers
+        .get(header::ETAG)
+        .and_then(|value| value.to_str().ok())
+        .filter(|etag| !etag.starts_with("W/"))
+        .map(String::from);
+    let cached = etag.as_deref().filter(|_| immutable).and_then(|etag| {
+        cache
+            .lock()
+            .expect("compression cache lock poisoned")
+            .get(etag, encoding)
+    });
+
+    let compressed = match cached {
+        Some(compressed) => compressed,
+        None => {
+            let encoded = tokio::task::spawn_blocking(move || encoding.encode(&body))
+                .await
+                .map_err(|err| Error::Compression(err.to_string()))?
+                .map_err(|err| Error::Compression(err.to_string()))?;
+            let compressed = Bytes::from(encoded);
+            if let (true, Some(etag)) = (immutable, etag.as_deref()) {
+                cache
+                    .lock()
+                    .expect("compression cache lock poisoned")
+                    .insert(etag, encoding, compressed.clone());
+            }
+            compressed
+        }
+    };
+
+    parts.headers.insert(
+        header::CONTENT_ENCODING,
+        HeaderValue::from_static(encoding.as_str()),
+    );
+    parts.headers.remove(header::CONTENT_LENGTH);
+    // the compressed body is no longer byte for byte the one the strong etag stands for
+    if let Some(etag) = etag {
+        if let Ok(weak) = HeaderValue::from_str(&format!("W/{}", etag)) {
+            parts.headers.insert(header::ETAG, weak);
+        }
+    }
+    Ok(Response::from_parts(parts, Body::from(compressed)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn decode(encoding: &str, body: &[u8]) -> String {
+        let mut decoded = String::new();
+        match encoding {
+            "br" => brotli::Decompressor::new(body, 4096)
+                .read_to_string(&mut decoded)
+                .unwrap(),
+            "zstd" => zstd::Decoder::new(body)
+                .unwrap()
+                .read_to_string(&mut decoded)
+                .unwrap(),
+            "gzip" => flate2::read::GzDecoder::new(body)
+                .read_to_string(&mut decoded)
+                .unwrap(),
+            _ => panic!("unexpected encoding {}", encoding),
+        };
+        decoded
+    }
+
+    #[test]
+    fn negotiate() {
+        assert_eq!(
+            Encoding::negotiate("gzip, deflate, br"),
+            Some(Encoding::Brotli)
+        );
+        assert_eq!(
+            Encoding::negotiate("gzip;q=1.0, br;q=0.5"),
+            Some(Encoding::Gzip)
+        );
+        assert_eq!(Encoding::negotiate("zstd, gzip"), Some(Encoding::Zstd));
+        assert_eq!(Encoding::negotiate("*;q=0.1, br;q=0"), Some(Encoding::Zstd));
+        assert_eq!(Encoding::negotiate("identity"), None);
+        assert_eq!(Encoding::negotiate(""), None);
+    }
+
+    #[tokio::test]
+    async fn wrap() {
+        let large = "1.1,".repeat(1000);
+        let routes = super::wrap(
+            warp::path("large")
+                .map(move || {
+                    warp::reply::with_header(
+                        warp::reply::with_header(large.clone(), "etag", "\"rates\""),
+                        "cache-control",
+                        "public, max-age=31536000, immutable",
+                    )
+                })
+                .or(warp::path("small").map(|| "1.1")),
+            Compression {
+                min_size: 100,
+                cache_size: 1024,
+            },
+        );
+
+        for encoding in ["br", "zstd", "gzip"] {
+            let response = warp::test::request()
+                .path("/large")
+                .header("accept-encoding", encoding)
+                .reply(&routes)
+                .await;
+            assert_eq!(response.headers()["content-encoding"], encoding);
+            assert_eq!(response.headers()["vary"], "accept-encoding");
+            assert_eq!(response.headers()["etag"], "W/\"rates\"");
+            assert_eq!(decode(encoding, response.body()), "1.1,".repeat(1000));
+        }
+
+        let response = warp::test::request().path("/large").reply(&routes).await;
+        assert!(!response.headers().contains_key("content-encoding"));
+        assert_eq!(response.headers()["vary"], "accept-encoding");
+        assert_eq!(response.body().len(), 4000);
+
+        let response = warp::test::request()
+            .path("/small")
+            .header("accept-encoding", "gzip")
+            .reply(&routes)
+            .await;
+        assert!(!response.headers().contains_key("content-encoding"));
+        assert_eq!(response.body(), "1.1");
+    }
+
+    #[test]
+    fn cache() {
+        let mut cache = Cache {
+            max_size: 10,
+            size: 0,
+            bodies: HashMap::new(),
+            order: VecDeque::new(),
+        };
+        cache.insert("a", Encoding::Gzip, Bytes::from_static(b"aaaa"));
+        cache.insert("b", Encoding::Gzip, Bytes::from_static(b"bbbb"));
+        cache.insert("b", Encoding::Brotli, Bytes::from_static(b"bb"));
+        assert_eq!(cache.size, 10);
+        assert_eq!(cache.get("a", Encoding::Brotli), None);
+
+        // evicts the oldest
+        cache.insert("c", Encoding::Gzip, Bytes::from_static(b"cccc"));
+        assert_eq!(cache.get("a", Encoding::Gzip), None);
+        assert_eq!(cache.get("c", Encoding::Gzip).unwrap(), "cccc");
+        assert_eq!(cache.size, 10);
+
+        // never fits
+        cache.insert("d", Encoding::Gzip, Bytes::from_static(b"ddddddddddd"));
+        assert_eq!(cache.get("d", Encoding::Gzip), None);
+        assert_eq!(cache.get("b", Encoding::Gzip).unwrap(), "bbbb");
+    }
+}
@@ -0,0 +1,419 @@
+// server configuration, the defaults are overridden by the TOML config file, then by the
+// environment variables and then by the command line flags. Clap reads each environment variable
+// along with its flag.
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::PathBuf;
+
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+use crate::caching::Caching;
+use crate::compression::Compression;
+use crate::cors::Cors;
+use crate::error::Error;
+use crate::fetcher::ECB_URL;
+
+#[derive(Debug, Default, Parser)]
+#[clap(
+    version,
+    about = "Currency rates API, from the European Central Bank's reference rates"
+)]
+pub struct Args {
+    #[clap(long, short, help = "TOML configuration file")]
+    pub config: Option<PathBuf>,
+    #[clap(long, env = "BIND_ADDRESS", help = "Address to listen on")]
+    pub bind_address: Option<IpAddr>,
+    #[clap(long, env = "PORT", help = "Port to listen on")]
+    pub port: Option<u16>,
+    #[clap(long, env = "DB_LOCATION", help = "Database location")]
+    pub db_location: Option<String>,
+    #[clap(long, env = "UPDATE_INTERVAL", help = "Seconds between rates updates")]
+    pub update_interval: Option<u64>,
+    #[clap(
+        long,
+        env = "NO_FETCH",
+        help = "Never fetch rates from the ECB, they only change through imports"
+    )]
+    pub no_fetch: bool,
+    #[clap(
+        long,
+        env = "ECB_URL",
+        help = "Location of the ECB's eurofxref xml files"
+    )]
+    pub ecb_url: Option<String>,
+    #[clap(
+        long,
+        env = "BOOTSTRAP_FILE",
+        help = "Local xml, csv or zip file of rates to create the database from"
+    )]
+    pub bootstrap_file: Option<String>,
+    #[clap(
+        long,
+        env = "CORS_ORIGINS",
+        value_delimiter = ',',
+        help = "Origins allowed to fetch the api, `*` for any"
+    )]
+    pub cors_origins: Option<Vec<String>>,
+    #[clap(
+        long,
+        env = "CORS_METHODS",
+        value_delimiter = ',',
+        help = "Methods allowed on cross-origin requests"
+    )]
+    pub cors_methods: Option<Vec<String>>,
+    #[clap(
+        long,
+        env = "CORS_MAX_AGE",
+        help = "Seconds browsers can cache preflight responses for"
+    )]
+    pub cors_max_age: Option<u32>,
+    #[clap(
+        long,
+        env = "COMPRESSION_MIN_SIZE",
+        help = "Smallest response body compressed, in bytes"
+    )]
+    pub compression_min_size: Option<usize>,
+    #[clap(
+        long,
+        env = "COMPRESSION_CACHE_SIZE",
+        help = "Memory for the compressed bodies of immutable responses, in bytes"
+    )]
+    pub compression_cache_size: Option<usize>,
+    #[clap(
+        long,
+        env = "CACHING_PUBLICATION_HOUR",
+        help = "Hour, in UTC, the rates are published at on working days"
+    )]
+    pub caching_publication_hour: Option<u32>,
+    #[clap(
+        long,
+        env = "CACHING_MIN_MAX_AGE",
+        help = "Seconds responses with overdue rates are cached for"
+    )]
+    pub caching_min_max_age: Option<u32>,
+    #[clap(
+        long,
+        env = "CACHING_IMMUTABLE_MAX_AGE",
+        help = "Seconds responses that never change are cached for"
+    )]
+    pub caching_immutable_max_age: Option<u32>,
+    #[clap(
+        long,
+        env = "RUST_LOG",
+        help = "Log filter, e.g. info or currencies=debug"
+    )]
+    pub log: Option<String>,
+    #[clap(
+        long,
+        value_name = "FILE",
+        help = "Import a local xml, csv or zip file of rates into the database and exit"
+    )]
+    pub import: Option<PathBuf>,
+    #[clap(long, help = "Print the effective configuration and exit")]
+    pub print_config: bool,
+}
+
+// values must come before the tables to serialize as TOML
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub bind_address: IpAddr,
+    pub port: u16,
+    pub db_location: String,
+    // env_logger filter
+    pub log: String,
+    pub update: Update,
+    pub source: Source,
+    pub cors: Cors,
+    pub compression: Compression,
+    pub caching: Caching,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Update {
+    // fetch new rates from the source, when disabled rates only change through imports
+    pub enabled: bool,
+    // seconds between checks for new rates
+    pub interval: u64,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Source {
+    // location of the ECB's eurofxref xml files, useful to point to a mirror
+    pub ecb_url: String,
+    // local file of rates to create the database from instead of fetching them, e.g.
+    // eurofxref-hist.zip
+    pub bootstrap_file: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            bind_address: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port: 3030,
+            db_location: "db".into(),
+            log: "error".into(),
+            update: Update::default(),
+            source: Source::default(),
+            cors: Cors::default(),
+            compression: Compression::default(),
+            caching: Caching::default(),
+        }
+    }
+}
+
+impl Default for Update {
+    fn default() -> Update {
+        Update {
+            enabled: true,
+            interval: 360,
+        }
+    }
+}
+
+impl Default for Source {
+    fn default() -> Source {
+        Source {
+            ecb_url: ECB_URL.into(),
+            bootstrap_file: None,
+        }
+    }
+}
+
+impl Config {
+    // `args` already hold the environment variables that have no flag set
+    pub fn load(args: &Args) -> Result<Config, Error> {
+        let mut config = match args.config {
+            Some(ref path) => {
+                let file = fs::read_to_string(path).map_err(|err| {
+                    Error::Config(format!("could not read {}, {}", path.display(), err))
+                })?;
+                toml::from_str(&file).map_err(|err| {
+                    Error::Config(format!("could not parse {}, {}", path.display(), err))
+                })?
+            }
+            None => Config::default(),
+        };
+
+        if let Some(bind_address) = args.bind_address {
+            config.bind_address = bind_address;
+        }
+        if let Some(port) = args.port {
+            config.port = port;
+        }
+        if let Some(ref db_location) = args.db_location {
+            config.db_location = db_location.clone();
+        }
+        if let Some(ref log) = args.log {
+            config.log = log.clone();
+        }
+        if let Some(interval) = args.update_interval {
+            config.update.interval = interval;
+        }
+        if args.no_fetch {
+            config.update.enabled = false;
+        }
+        if let Some(ref ecb_url) = args.ecb_url {
+            config.source.ecb_url = ecb_url.clone();
+        }
+        if let Some(ref bootstrap_file) = args.bootstrap_file {
+            config.source.bootstrap_file = Some(bootstrap_file.clone());
+        }
+        if let Some(ref origins) = args.cors_origins {
+            config.cors.origins = list(origins);
+        }
+        if let Some(ref methods) = args.cors_methods {
+            config.cors.methods = list(methods);
+        }
+        if let Some(max_age) = args.cors_max_age {
+            config.cors.max_age = max_age;
+        }
+        if let Some(min_size) = args.compression_min_size {
+            config.compression.min_size = min_size;
+        }
+        if let Some(cache_size) = args.compression_cache_size {
+            config.compression.cache_size = cache_size;
+        }
+        if let Some(publication_hour) = args.caching_publication_hour {
+            config.caching.publication_hour = publication_hour;
+        }
+        if let Some(min_max_age) = args.caching_min_max_age {
+            config.caching.min_max_age = min_max_age;
+        }
+        if let Some(immutable_max_age) = args.caching_immutable_max_age {
+            config.caching.immutable_max_age = immutable_max_age;
+        }
+
+        if config.update.interval == 0 {
+            return Err(Error::Config(
+                "update interval must be at least 1 second".into(),
+            ));
+        }
+        if config.caching.publication_hour > 23 {
+            return Err(Error::Config(format!(
+                "`{}` is an invalid publication hour, must be between 0 and 23",
+                config.caching.publication_hour
+            )));
+        }
+        Ok(config)
+    }
+
+    pub fn to_toml(&self) -> Result<String, Error> {
+        toml::to_string_pretty(self)
+            .map_err(|err| Error::Config(format!("could not serialize configuration, {}", err)))
+    }
+}
+
+// comma separated values, e.g. `GET, POST`
+fn list(values: &[String]) -> Vec<String> {
+    values
+        .iter()
+        .map(|value| value.trim())
+        .filter(|value| !value.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn load() {
+        let config = Config::load(&Args::default()).unwrap();
+        assert_eq!(config, Config::default());
+
+        let mut file = NamedTempFile::new().unwrap();
+        write!(
+            file,
+            r#"
+            port = 8080
+            db_location = "/var/lib/currencies"
+
+            [update]
+            interval = 600
+
+            [source]
+            bootstrap_file = "eurofxref-hist.zip"
+
+            [cors]
+            origins = ["https://example.com"]
+
+            [compression]
+            min_size = 512
+
+            [caching]
+            min_max_age = 120
+            "#
+        )
+        .unwrap();
+        let args = Args {
+            config: Some(file.path().to_path_buf()),
+            port: Some(9090),
+            db_location: Some("/tmp/db".into()),
+            no_fetch: true,
+            bootstrap_file: Some("/srv/eurofxref-hist.xml".into()),
+            caching_immutable_max_age: Some(86400),
+            ..Args::default()
+        };
+        // flags override the file
+        let config = Config::load(&args).unwrap();
+        assert_eq!(config.port, 9090);
+        assert_eq!(config.db_location, "/tmp/db");
+        assert_eq!(config.log, "error");
+        assert_eq!(config.update.interval, 600);
+        assert!(!config.update.enabled);
+        assert_eq!(
+            config.source.bootstrap_file.as_deref(),
+            Some("/srv/eurofxref-hist.xml")
+        );
+        assert_eq!(config.cors.origins, vec!["https://example.com"]);
+        assert_eq!(config.cors.methods, Cors::default().methods);
+        assert_eq!(config.compression.min_size, 512);
+        assert_eq!(
+            config.compression.cache_size,
+            Compression::default().cache_size
+        );
+        assert_eq!(config.caching.min_max_age, 120);
+        assert_eq!(config.caching.immutable_max_age, 86400);
+        assert_eq!(
+            config.caching.publication_hour,
+            Caching::default().publication_hour
+        );
+    }
+
+    #[test]
+    fn load_invalid() {
+        let invalid = |args: Args| Config::load(&args);
+        assert!(matches!(
+            invalid(Args {
+                update_interval: Some(0),
+                ..Args::default()
+            }),
+            Err(Error::Config(_))
+        ));
+        assert!(matches!(
+            invalid(Args {
+                caching_publication_hour: Some(24),
+                ..Args::default()
+            }),
+            Err(Error::Config(_))
+        ));
+
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, "prot = 8080").unwrap();
+        assert!(matches!(
+            invalid(Args {
+                config: Some(file.path().to_path_buf()),
+                ..Args::default()
+            }),
+            Err(Error::Config(_))
+        ));
+    }
+
+    #[test]
+    fn args() {
+        Args::command().debug_assert();
+
+        // the only test reading these variables
+        let vars = [
+            ("CORS_ORIGINS", "https://example.com, https://example.org"),
+            ("CACHING_MIN_MAX_AGE", "120"),
+            ("NO_FETCH", "true"),
+        ];
+        for (key, value) in vars {
+            std::env::set_var(key, value);
+        }
+        let args = Args::try_parse_from(["currencies", "--caching-min-max-age", "300"]);
+        for (key, _) in vars {
+            std::env::remove_var(key);
+        }
+
+        let config = Config::load(&args.unwrap()).unwrap();
+        assert_eq!(
+            config.cors.origins,
+            vec!["https://example.com", "https://example.org"]
+        );
+        // flags override the environment
+        assert_eq!(config.caching.min_max_age, 300);
+        assert!(!config.update.enabled);
+
+        assert!(Args::try_parse_from(["currencies", "--port", "http"]).is_err());
+        assert!(Args::try_parse_from(["currencies", "--bind-address", "localhost"]).is_err());
+    }
+
+    #[test]
+    fn to_toml() {
+        let config = Config::default();
+        let printed = config.to_toml().unwrap();
+        assert!(printed.contains("[cors]"));
+        assert!(printed.contains("[caching]"));
+        assert_eq!(toml::from_str::<Config>(&printed).unwrap(), config);
+    }
+}
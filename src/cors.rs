@@ -0,0 +1,157 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use warp::http::{Method, Uri};
+
+use crate::error::Error;
+
+// request headers browsers may send on api requests, besides the CORS-safelisted ones
+const ALLOWED_HEADERS: &[&str] = &[
+    "accept",
+    "content-type",
+    "if-none-match",
+    "if-modified-since",
+];
+
+// response headers exposed to browsers, besides the CORS-safelisted ones
+const EXPOSED_HEADERS: &[&str] = &["etag"];
+
+// cross-origin access to the api, by default any origin can read it as it's public
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Cors {
+    // `*` allows any origin
+    pub origins: Vec<String>,
+    pub methods: Vec<String>,
+    // seconds browsers can cache preflight responses for
+    pub max_age: u32,
+}
+
+impl Default for Cors {
+    fn default() -> Cors {
+        Cors {
+            origins: vec!["*".into()],
+            methods: vec!["GET".into(), "HEAD".into(), "POST".into()],
+            max_age: 24 * 60 * 60,
+        }
+    }
+}
+
+impl Cors {
+    // validated beforehand as warp panics on invalid origins and methods
+    pub fn filter(&self) -> Result<warp::cors::Cors, Error> {
+        let methods = self
+            .methods
+            .iter()
+            .map(|method| {
+                Method::from_str(&method.to_uppercase())
+                    .map_err(|_| Error::InvalidCors(format!("`{}` is an invalid method", method)))
+            })
+            .collect::<Result<Vec<Method>, Error>>()?;
+
+        let builder = warp::cors()
+            .allow_methods(methods)
+            .allow_headers(ALLOWED_HEADERS.iter().copied())
+            .expose_headers(EXPOSED_HEADERS.iter().copied())
+            .max_age(self.max_age);
+        let builder = if self.origins.iter().any(|origin| origin == "*") {
+            builder.allow_any_origin()
+        } else {
+            for origin in &self.origins {
+                validate_origin(origin)?;
+            }
+            builder.allow_origins(self.origins.iter().map(String::as_str))
+        };
+        Ok(builder.build())
+    }
+}
+
+// origins are a scheme and a host, with an optional port, e.g. `https://example.com`
+fn validate_origin(origin: &str) -> Result<(), Error> {
+    let valid = Uri::from_str(origin).is_ok_and(|uri| {
+        uri.scheme().is_some()
+            && uri.authority().is_some()
+            && uri.path() == "/"
+            && uri.query().is_none()
+            && !origin.ends_with('/')
+    });
+    if !valid {
+        return Err(Error::InvalidCors(format!(
+            "`{}` is an invalid origin",
+            origin
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use warp::Filter;
+
+    #[test]
+    fn validate_origin() {
+        assert!(super::validate_origin("https://example.com").is_ok());
+        assert!(super::validate_origin("http://localhost:8080").is_ok());
+        assert!(super::validate_origin("example.com").is_err());
+        assert!(super::validate_origin("https://example.com/").is_err());
+        assert!(super::validate_origin("https://example.com/path").is_err());
+    }
+
+    #[tokio::test]
+    async fn filter() {
+        let cors = Cors {
+            origins: vec!["https://example.com".into()],
+            methods: vec!["get".into()],
+            max_age: 60,
+        };
+        let routes = warp::any().map(warp::reply).with(cors.filter().unwrap());
+
+        let response = warp::test::request()
+            .method("OPTIONS")
+            .header("origin", "https://example.com")
+            .header("access-control-request-method", "GET")
+            .header("access-control-request-headers", "if-none-match")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            response.headers()["access-control-allow-origin"],
+            "https://example.com"
+        );
+        assert_eq!(response.headers()["access-control-max-age"], "60");
+
+        let response = warp::test::request()
+            .method("OPTIONS")
+            .header("origin", "https://example.com")
+            .header("access-control-request-method", "DELETE")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 403);
+
+        let response = warp::test::request()
+            .header("origin", "https://other.com")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 403);
+
+        let response = warp::test::request()
+            .header("origin", "https://other.com")
+            .reply(
+                &warp::any()
+                    .map(warp::reply)
+                    .with(Cors::default().filter().unwrap()),
+            )
+            .await;
+        assert_eq!(
+            response.headers()["access-control-allow-origin"],
+            "https://other.com"
+        );
+
+        let cors = Cors {
+            methods: vec!["NOT A METHOD".into()],
+            ..Cors::default()
+        };
+        assert!(cors.filter().is_err());
+    }
+}
@@ -1,4 +1,5 @@
 use std::cmp::Ordering;
+use std::collections::BTreeMap;
 use std::path::Path;
 use std::str::FromStr;
 use std::sync::Arc;
@@ -7,10 +8,29 @@ use std::sync::Arc;
 use crate::error::Error;
 use chrono::naive::NaiveDate;
 use chrono::Duration;
-use serde::{de::DeserializeOwned, Serialize};
+use futures::stream::{self, TryStreamExt};
+use rust_decimal::Decimal;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use sled::IVec;
 
-use crate::fetcher::{self, Currency, Date};
+use crate::fetcher::{Currency, Date, DateStream, Fetched, RateSource, Validators, ECB_DAILY};
+
+// database format version, stored on the `version` key, databases without it are from before
+// rates were stored as decimals and version 1 ones from before the currency spans were kept
+const VERSION: u32 = 2;
+
+// sled tree holding the validators of the source documents, keyed by their url
+const VALIDATORS_TREE: &str = "validators";
+
+// sled tree holding the first and last date each currency has a stored rate on, keyed by the
+// currency code, so they don't have to be read from every stored date
+const SPANS_TREE: &str = "spans";
+
+// first and last date each currency has a stored rate on, by currency code
+pub type Spans = BTreeMap<String, (String, String)>;
+
+// date keys are big endian timestamps, other keys like `current` have different lengths
+const DATE_KEY_LEN: usize = 8;
 
 pub fn date_as_key(date: &str) -> Result<Vec<u8>, Error> {
     let date = NaiveDate::from_str(date)
@@ -22,88 +42,217 @@ pub fn date_as_key(date: &str) -> Result<Vec<u8>, Error> {
     Ok(date)
 }
 
-pub async fn init<P: AsRef<Path>>(path: P) -> Result<Db, Error> {
+pub async fn init<P: AsRef<Path>>(path: P, source: &dyn RateSource) -> Result<Db, Error> {
     if path.as_ref().exists() {
         log::info!("previous db file found, going to open it");
-        Db::open(path)
+        let db = Db::open(path)?;
+        db.migrate().await?;
+        Ok(db)
     } else {
-        bootstrap_new(path).await
+        bootstrap_new(path, source).await
     }
 }
 
-// bootstrap a new database by fetching all histrical reference rates from ECB
-async fn bootstrap_new<P: AsRef<Path>>(path: P) -> Result<Db, Error> {
+// bootstrap a new database by fetching all histrical reference rates from the source, the ECB or a
+// local file, rates are stored as they are downloaded
+async fn bootstrap_new<P: AsRef<Path>>(path: P, source: &dyn RateSource) -> Result<Db, Error> {
     log::info!("no database found, going to bootstrap a new one");
-    log::info!("dowloading ECB's currency values since 99");
-    let dates = crate::fetcher::fetch_hist().await.map_err(|err| {
+    log::info!("dowloading all historical currency values");
+    let fetch_error = |err: Error| {
         Error::Database(
-            "could not fetch Historical reference rates from ECB".into(),
+            "could not fetch Historical reference rates from source".into(),
             Some(err.into()),
         )
-    })?;
+    };
+    let dates = source.history().await.map_err(fetch_error)?;
 
     log::info!("populating new db with currency values");
-    let current_date = dates.first().ok_or_else(|| {
-        Error::Database(
-            "fetched Historical reference rates from ECB are empy".into(),
-            None,
-        )
-    })?;
+    let db = Db::open(&path)?;
+    let populated = async {
+        db.put(b"version", &VERSION).await?;
+        let current = store_dates(&db, dates, None)
+            .await
+            .map_err(|err| match err {
+                err @ Error::Fetcher(_) => fetch_error(err),
+                err => err,
+            })?
+            .ok_or_else(|| {
+                Error::Database(
+                    "fetched Historical reference rates from source are empy".into(),
+                    None,
+                )
+            })?;
+        // only marked current once all the rates are stored
+        db.put(b"current", &date_as_key(&current)?).await?;
+        db.inner
+            .flush_async()
+            .await
+            .map_err(|err| Error::Database("could not flush database".into(), Some(err.into())))
+    }
+    .await;
 
-    let db = Db::open(path)?;
+    if let Err(err) = populated {
+        // don't leave a partial database behind, it would be opened as is on the next start
+        drop(db);
+        if let Err(err) = std::fs::remove_dir_all(&path) {
+            log::error!("could not remove partially bootstrapped database, {}", err);
+        }
+        return Err(err);
+    }
+    Ok(db)
+}
+
+// store the dates newer than `after`, adding the EUR base to each, returns the newest stored date
+async fn store_dates(
+    db: &Db,
+    dates: DateStream,
+    after: Option<NaiveDate>,
+) -> Result<Option<String>, Error> {
+    let mut spans = Spans::new();
+    let stored = store_dates_spans(db, dates, after, &mut spans).await;
+    // the spans of the dates stored before an error are kept too
+    db.merge_spans(spans).await?;
+    stored
+}
 
-    db.put(b"current", &date_as_key(&current_date.value)?)
-        .await?;
-    for mut date in dates {
-        let day = &date_as_key(&date.value)?;
+async fn store_dates_spans(
+    db: &Db,
+    mut dates: DateStream,
+    after: Option<NaiveDate>,
+    spans: &mut Spans,
+) -> Result<Option<String>, Error> {
+    let mut newest = None;
+    while let Some(mut date) = dates.try_next().await? {
+        // dates come newest first
+        if let Some(after) = after {
+            if date.value_as_date()? <= after {
+                break;
+            }
+        }
+
+        let day = date_as_key(&date.value)?;
         //insert EUR base
         date.currencies.push(Currency {
             name: "EUR".to_string(),
-            rate: 1.0,
+            rate: Decimal::ONE,
         });
-        db.put(day, &date).await?;
+        db.put(&day, &date).await?;
+        for currency in &date.currencies {
+            extend_span(spans, &currency.name, &date.value, &date.value);
+        }
+        // a bootstrap inserts the whole history, only updates are logged per date
+        if after.is_some() {
+            log::info!("inserted rates for {}", date.value);
+        }
+        if newest.as_ref().is_none_or(|newest| &date.value > newest) {
+            newest = Some(date.value);
+        }
     }
+    Ok(newest)
+}
+
+// store the rates of the source, e.g. a local file, newer than the current ones on an existing
+// database. Responses covering past dates are cached as immutable, so dates before the current ones
+// can't gain rates and stored dates can't change, the source may only repeat them as they are. The
+// new dates are only stored once the whole source is checked.
+pub async fn import(db: &Db, source: &dyn RateSource) -> Result<(), Error> {
+    let current = db.get_current_rates().await?.value;
+    let mut dates = source.history().await?;
+    let mut batch = sled::Batch::default();
+    let mut spans = Spans::new();
+    let mut found = false;
+    let mut newest: Option<String> = None;
+    while let Some(mut date) = dates.try_next().await? {
+        found = true;
+        match db.stored_rates(&date).await? {
+            Some(true) => continue,
+            Some(false) => {
+                return Err(Error::Import(format!(
+                    "rates for {} differ from the stored ones",
+                    date.value
+                )))
+            }
+            None if date.value < current => {
+                return Err(Error::Import(format!(
+                    "rates for {} are missing from the database, dates before the current {} can't be added",
+                    date.value, current
+                )))
+            }
+            None => {}
+        }
+
+        //insert EUR base
+        date.currencies.push(Currency {
+            name: "EUR".to_string(),
+            rate: Decimal::ONE,
+        });
+        batch.insert(date_as_key(&date.value)?, encode(&date.value, &date)?);
+        for currency in &date.currencies {
+            extend_span(&mut spans, &currency.name, &date.value, &date.value);
+        }
+        if newest.as_ref().is_none_or(|newest| &date.value > newest) {
+            newest = Some(date.value);
+        }
+    }
+    if !found {
+        return Err(Error::Import("no rates found to import".into()));
+    }
+    let newest = match newest {
+        Some(newest) => newest,
+        None => {
+            log::info!("all the imported rates were already stored");
+            return Ok(());
+        }
+    };
+
+    batch.insert(&b"current"[..], encode("current", &date_as_key(&newest)?)?);
+    db.apply_batch(batch).await?;
+    db.merge_spans(spans).await?;
     db.inner
         .flush_async()
         .await
         .map_err(|err| Error::Database("could not flush database".into(), Some(err.into())))?;
 
-    Ok(db)
+    log::info!("imported rates up to {}", newest);
+    Ok(())
 }
 
 // check if there are any missing currencies days and if so fetch and add them to the database
-pub async fn update(db: &Db) -> Result<(), Error> {
-    let current = fetcher::fetch_daily().await?.value_as_date()?;
+pub async fn update(db: &Db, source: &dyn RateSource) -> Result<(), Error> {
+    let url = source.url(ECB_DAILY);
+    let cached = db.get_validators(&url).await?.unwrap_or_default();
+    let (daily, validators) = match source.daily(&cached).await? {
+        Fetched::NotModified => {
+            log::debug!("daily rates not modified since last fetch");
+            return Ok(());
+        }
+        Fetched::Modified { dates, validators } => {
+            (dates.try_collect::<Vec<Date>>().await?, validators)
+        }
+    };
+    let current = daily
+        .first()
+        .ok_or_else(|| Error::Fetcher("Daily rates are empty".into()))?
+        .value_as_date()?;
     let db_current = db.get_current_rates().await?.value_as_date()?;
 
     match current.cmp(&db_current) {
         Ordering::Equal => {
             log::debug!("database currencies up to date");
-            return Ok(());
         }
 
         Ordering::Greater => {
             log::debug!("going to update database with new currencies");
-            let mut dates = match current - db_current {
-                d if d > Duration::days(90) => fetcher::fetch_hist().await?,
-                d if d < Duration::days(90) && d > Duration::days(1) => {
-                    fetcher::fetch_last90().await?
-                }
-                _ => vec![fetcher::fetch_daily().await?],
+            let dates = match current - db_current {
+                d if d >= Duration::days(90) => source.history().await?,
+                d if d < Duration::days(90) && d > Duration::days(1) => source.last90().await?,
+                _ => Box::pin(stream::iter(daily.into_iter().map(Ok))),
             };
 
-            for date in dates.iter_mut().rev() {
-                if date.value_as_date()? > db_current {
-                    let day = &date_as_key(&date.value)?;
-                    //insert EUR base
-                    date.currencies.push(Currency {
-                        name: "EUR".to_string(),
-                        rate: 1.0,
-                    });
-                    db.put(day, &date).await?;
-                    db.put(b"current", &date_as_key(&date.value)?).await?;
-                    log::info!("inserted rates for {}", date.value.to_string());
-                }
+            // current is only moved once all the missing dates are stored, so that an interrupted
+            // update is retried from the same point
+            if let Some(current) = store_dates(db, dates, Some(db_current)).await? {
+                db.put(b"current", &date_as_key(&current)?).await?;
             }
         }
         Ordering::Less => {
@@ -113,7 +262,46 @@ pub async fn update(db: &Db) -> Result<(), Error> {
             ))
         }
     }
-    Ok(())
+
+    // only kept once the rates are stored, a failed update must fetch them again
+    db.put_validators(&url, &validators).await
+}
+
+// widen the span of `name` to include `first` and `last`
+fn extend_span(spans: &mut Spans, name: &str, first: &str, last: &str) {
+    match spans.get_mut(name) {
+        Some(span) => {
+            if first < span.0.as_str() {
+                span.0 = first.to_string();
+            }
+            if last > span.1.as_str() {
+                span.1 = last.to_string();
+            }
+        }
+        None => {
+            spans.insert(name.to_string(), (first.to_string(), last.to_string()));
+        }
+    }
+}
+
+// direction to walk the database keys when looking for the nearest published date
+#[derive(Clone, Copy, Debug)]
+pub enum Direction {
+    Backward,
+    Forward,
+}
+
+// rates as stored before VERSION 1
+#[derive(Deserialize)]
+struct LegacyDate {
+    value: String,
+    currencies: Vec<LegacyCurrency>,
+}
+
+#[derive(Deserialize)]
+struct LegacyCurrency {
+    name: String,
+    rate: f64,
 }
 
 #[derive(Clone)]
@@ -131,6 +319,86 @@ impl Db {
         Ok(db)
     }
 
+    // convert databases from previous versions to the current format
+    async fn migrate(&self) -> Result<(), Error> {
+        let version = self.get::<u32>(b"version").await?;
+        if version == Some(VERSION) {
+            return Ok(());
+        }
+
+        if version.is_none() {
+            self.migrate_decimals().await?;
+        }
+        if version.is_none_or(|version| version < 2) {
+            log::info!("migrating database to keep the currency spans");
+            let spans = self.scan_spans().await?;
+            self.merge_spans(spans).await?;
+        }
+
+        self.put(b"version", &VERSION).await?;
+        self.inner
+            .flush_async()
+            .await
+            .map_err(|err| Error::Database("could not flush database".into(), Some(err.into())))?;
+        Ok(())
+    }
+
+    async fn migrate_decimals(&self) -> Result<(), Error> {
+        log::info!("migrating database rates to decimals");
+        let entries = self
+            .execute(|db| {
+                db.iter()
+                    .collect::<Result<Vec<(IVec, IVec)>, sled::Error>>()
+            })
+            .await
+            .map_err(|err| {
+                Error::Database(
+                    "could not read database to migrate".into(),
+                    Some(err.into()),
+                )
+            })?;
+
+        let mut batch = sled::Batch::default();
+        for (key, value) in entries {
+            if key.len() != DATE_KEY_LEN {
+                continue;
+            }
+            let legacy = bincode::deserialize::<LegacyDate>(&value).map_err(|err| {
+                Error::Database(
+                    "could not deserialize legacy rates".into(),
+                    Some(err.into()),
+                )
+            })?;
+            let currencies = legacy
+                .currencies
+                .into_iter()
+                .map(|currency| {
+                    // f64 display is the shortest representation that round trips, which is the
+                    // published rate
+                    let rate = Decimal::from_str(&currency.rate.to_string()).map_err(|err| {
+                        Error::Database(
+                            format!("could not convert rate {} to decimal", currency.rate),
+                            Some(err.into()),
+                        )
+                    })?;
+                    Ok(Currency {
+                        name: currency.name,
+                        rate,
+                    })
+                })
+                .collect::<Result<Vec<Currency>, Error>>()?;
+            let date = Date {
+                value: legacy.value,
+                currencies,
+            };
+            batch.insert(key, encode(&date.value, &date)?);
+        }
+        // rows are only decoded as legacy ones while there is no version, so the rewrites and the
+        // version are written together for an interrupted migration to start over
+        batch.insert(&b"version"[..], encode("version", &1u32)?);
+        self.apply_batch(batch).await
+    }
+
     pub async fn get_current_rates(&self) -> Result<Date, Error> {
         let current = self.get::<Vec<u8>>(b"current").await?.ok_or_else(|| {
             Error::Database("could not find `current` key on the database".into(), None)
@@ -153,6 +421,71 @@ impl Db {
         }
     }
 
+    // whether the rates of `date` are already stored as they are, `None` if its day isn't stored.
+    // Rates are compared with their scale and in any order, `date` lacks the EUR base.
+    async fn stored_rates(&self, date: &Date) -> Result<Option<bool>, Error> {
+        let stored = match self.get_day_rates(&date.value).await? {
+            Some(stored) => stored,
+            None => return Ok(None),
+        };
+        let rates = |currencies: &[Currency]| {
+            currencies
+                .iter()
+                .filter(|currency| currency.name != "EUR")
+                .map(|currency| (currency.name.clone(), currency.rate.to_string()))
+                .collect::<BTreeMap<String, String>>()
+        };
+        Ok(Some(rates(&stored.currencies) == rates(&date.currencies)))
+    }
+
+    // get the rates for `day` or, if there are none, for the nearest day before or after it
+    pub async fn get_nearest_day_rates(
+        &self,
+        day: &str,
+        direction: Direction,
+    ) -> Result<Option<Date>, Error> {
+        let key = date_as_key(day)?;
+
+        let entry = self
+            .execute(move |db| {
+                let mut range: Box<dyn Iterator<Item = sled::Result<(IVec, IVec)>>> =
+                    match direction {
+                        Direction::Backward => Box::new(db.range(..=key).rev()),
+                        Direction::Forward => Box::new(db.range(key..)),
+                    };
+                // skip keys that are not dates, like `current`
+                range
+                    .find(|result| match result {
+                        Ok((key, _)) => key.len() == DATE_KEY_LEN,
+                        Err(_) => true,
+                    })
+                    .transpose()
+            })
+            .await
+            .map_err(|err| {
+                Error::Database(
+                    "could not get nearest date from db".into(),
+                    Some(err.into()),
+                )
+            })?;
+
+        match entry {
+            Some((key, value)) => {
+                let date = bincode::deserialize::<Date>(&value).map_err(|err| {
+                    Error::Database(
+                        format!(
+                            "could not deseiralize database key: {}",
+                            String::from_utf8_lossy(&key)
+                        ),
+                        Some(err.into()),
+                    )
+                })?;
+                Ok(Some(date))
+            }
+            None => Ok(None),
+        }
+    }
+
     pub async fn get_range_rates(
         &self,
         start_at: NaiveDate,
@@ -185,20 +518,150 @@ impl Db {
         Ok(dates)
     }
 
+    // first and last date each currency has a stored rate on, by currency code
+    pub async fn currency_spans(&self) -> Result<Spans, Error> {
+        self.execute(move |db| {
+            let tree = db.open_tree(SPANS_TREE).map_err(|err| {
+                Error::Database("could not open currency spans".into(), Some(err.into()))
+            })?;
+            tree.iter()
+                .map(|result| {
+                    let (name, span) = result.map_err(|err| {
+                        Error::Database("could not iterate currency spans".into(), Some(err.into()))
+                    })?;
+                    let span = bincode::deserialize::<(String, String)>(&span).map_err(|err| {
+                        Error::Database(
+                            "could not deserialize currency span".into(),
+                            Some(err.into()),
+                        )
+                    })?;
+                    Ok((String::from_utf8_lossy(&name).into_owned(), span))
+                })
+                .collect()
+        })
+        .await
+    }
+
+    // widen the stored currency spans with `spans`
+    async fn merge_spans(&self, spans: Spans) -> Result<(), Error> {
+        if spans.is_empty() {
+            return Ok(());
+        }
+        let mut stored = self.currency_spans().await?;
+        for (name, (first, last)) in &spans {
+            extend_span(&mut stored, name, first, last);
+        }
+
+        let encoded = stored
+            .into_iter()
+            .filter(|(name, _)| spans.contains_key(name))
+            .map(|(name, span)| {
+                let span = bincode::serialize(&span).map_err(|err| {
+                    Error::Database("could not serialize currency span".into(), Some(err.into()))
+                })?;
+                Ok((name, span))
+            })
+            .collect::<Result<Vec<(String, Vec<u8>)>, Error>>()?;
+        self.execute(move |db| {
+            let tree = db.open_tree(SPANS_TREE)?;
+            let mut batch = sled::Batch::default();
+            for (name, span) in encoded {
+                batch.insert(name.as_bytes(), span);
+            }
+            tree.apply_batch(batch)
+        })
+        .await
+        .map_err(|err| Error::Database("could not put currency spans".into(), Some(err.into())))
+    }
+
+    // the currency spans read from every stored date
+    async fn scan_spans(&self) -> Result<Spans, Error> {
+        self.execute(move |db| {
+            let mut spans = Spans::new();
+            for result in db.iter() {
+                let (key, value) = result.map_err(|err| {
+                    Error::Database("could not iterate the database".into(), Some(err.into()))
+                })?;
+                // skip keys that are not dates, like `current`
+                if key.len() != DATE_KEY_LEN {
+                    continue;
+                }
+                let date = bincode::deserialize::<Date>(&value).map_err(|err| {
+                    Error::Database(
+                        format!(
+                            "could not deseiralize database key: {}",
+                            String::from_utf8_lossy(&key)
+                        ),
+                        Some(err.into()),
+                    )
+                })?;
+                for currency in &date.currencies {
+                    extend_span(&mut spans, &currency.name, &date.value, &date.value);
+                }
+            }
+            Ok(spans)
+        })
+        .await
+    }
+
+    // number of days with stored rates
+    pub async fn count_days(&self) -> Result<usize, Error> {
+        self.execute(move |db| {
+            // skip keys that are not dates, like `current`
+            db.iter().keys().try_fold(0, |count, key| {
+                key.map(|key| count + (key.len() == DATE_KEY_LEN) as usize)
+            })
+        })
+        .await
+        .map_err(|err| {
+            Error::Database(
+                "could not count days on the database".into(),
+                Some(err.into()),
+            )
+        })
+    }
+
+    async fn get_validators(&self, url: &str) -> Result<Option<Validators>, Error> {
+        let url = url.to_string();
+        let blob = self
+            .execute(move |db| db.open_tree(VALIDATORS_TREE)?.get(url))
+            .await
+            .map_err(|err| {
+                Error::Database("could not get source validators".into(), Some(err.into()))
+            })?;
+        blob.map(|blob| {
+            bincode::deserialize(&blob).map_err(|err| {
+                Error::Database(
+                    "could not deserialize source validators".into(),
+                    Some(err.into()),
+                )
+            })
+        })
+        .transpose()
+    }
+
+    async fn put_validators(&self, url: &str, validators: &Validators) -> Result<(), Error> {
+        let url = url.to_string();
+        let encoded = bincode::serialize(validators).map_err(|err| {
+            Error::Database(
+                "could not serialize source validators".into(),
+                Some(err.into()),
+            )
+        })?;
+        self.execute(move |db| db.open_tree(VALIDATORS_TREE)?.insert(url, encoded))
+            .await
+            .map_err(|err| {
+                Error::Database("could not put source validators".into(), Some(err.into()))
+            })?;
+        Ok(())
+    }
+
     async fn put<T>(&self, key: &[u8], value: &T) -> Result<Option<IVec>, Error>
     where
         T: Serialize,
     {
         let key = key.to_vec();
-        let encoded: Vec<u8> = bincode::serialize(value).map_err(|err| {
-            Error::Database(
-                format!(
-                    "could not bincode serialize key {}",
-                    String::from_utf8_lossy(&key)
-                ),
-                Some(err.into()),
-            )
-        })?;
+        let encoded = encode(&String::from_utf8_lossy(&key), value)?;
 
         self.execute({
             let key = key.clone();
@@ -216,6 +679,13 @@ impl Db {
         })
     }
 
+    // apply all the writes of `batch` atomically
+    async fn apply_batch(&self, batch: sled::Batch) -> Result<(), Error> {
+        self.execute(move |db| db.apply_batch(batch))
+            .await
+            .map_err(|err| Error::Database("could not apply batch".into(), Some(err.into())))
+    }
+
     async fn get<T>(&self, key: &[u8]) -> Result<Option<T>, Error>
     where
         T: DeserializeOwned + Send + 'static,
@@ -266,11 +736,37 @@ impl Db {
     }
 }
 
+fn encode<T: Serialize>(key: &str, value: &T) -> Result<Vec<u8>, Error> {
+    bincode::serialize(value).map_err(|err| {
+        Error::Database(
+            format!("could not bincode serialize key {}", key),
+            Some(err.into()),
+        )
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::fetcher::{Ecb, ECB_DAILY, ECB_HIST, ECB_HIST_LAST_90};
+    use crate::import::LocalFile;
+    use crate::mock_ecb::{self, MockEcb};
     use tempfile::tempdir;
 
+    // seed dates published up to `day`
+    fn seed_dates_until(day: &str) -> Vec<Date> {
+        mock_ecb::seed_dates()
+            .into_iter()
+            .filter(|date| date.value.as_str() <= day)
+            .collect()
+    }
+
+    async fn stored_days(db: &Db) -> usize {
+        let start = NaiveDate::from_ymd(1999, 1, 4);
+        let end = NaiveDate::from_ymd(2019, 12, 31);
+        db.get_range_rates(start, end).await.unwrap().len()
+    }
+
     #[test]
     fn _date_as_key() {
         let key = date_as_key("1999-01-04").unwrap();
@@ -359,4 +855,370 @@ mod tests {
         let dates = db.get_range_rates(begining, end).await.unwrap();
         assert_eq!(dates.len(), 3);
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn count_days() {
+        let dir = tempdir().unwrap();
+        let db = Db::open(dir.path().join("db")).unwrap();
+        assert_eq!(db.count_days().await.unwrap(), 0);
+
+        for day in ["1999-01-04", "2003-01-06", "2012-01-04"] {
+            let date = Date {
+                value: day.to_string(),
+                currencies: Vec::new(),
+            };
+            db.put(&date_as_key(day).unwrap(), &date).await.unwrap();
+        }
+        // keys that are not dates aren't counted
+        db.put(b"current", &date_as_key("2012-01-04").unwrap())
+            .await
+            .unwrap();
+        assert_eq!(db.count_days().await.unwrap(), 3);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn get_nearest_day_rates() {
+        let dir = tempdir().unwrap();
+        let path = dir.into_path();
+        let db = Db::open(path.join("db")).unwrap();
+
+        for day in &["2019-10-17", "2019-10-18", "2019-10-21"] {
+            let date = Date {
+                value: day.to_string(),
+                currencies: Vec::new(),
+            };
+            let key = date_as_key(&date.value).unwrap();
+            db.put(&key, &date).await.unwrap();
+        }
+        db.put(b"current", &date_as_key("2019-10-21").unwrap())
+            .await
+            .unwrap();
+        db.inner.flush_async().await.unwrap();
+
+        let date = db
+            .get_nearest_day_rates("2019-10-19", Direction::Backward)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(date.value, "2019-10-18");
+
+        let date = db
+            .get_nearest_day_rates("2019-10-19", Direction::Forward)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(date.value, "2019-10-21");
+
+        let date = db
+            .get_nearest_day_rates("2019-10-17", Direction::Forward)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(date.value, "2019-10-17");
+
+        let date = db
+            .get_nearest_day_rates("2019-10-22", Direction::Forward)
+            .await
+            .unwrap();
+        assert!(date.is_none());
+
+        let date = db
+            .get_nearest_day_rates("2019-10-16", Direction::Backward)
+            .await
+            .unwrap();
+        assert!(date.is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn currency_spans() {
+        let dir = tempdir().unwrap();
+        let path = dir.into_path();
+        let db = Db::open(path.join("db")).unwrap();
+
+        let currencies = |names: &[&str]| {
+            names
+                .iter()
+                .map(|name| Currency {
+                    name: name.to_string(),
+                    rate: Decimal::ONE,
+                })
+                .collect()
+        };
+        let dates = |days: &[(&str, &[&str])]| -> DateStream {
+            let dates = days
+                .iter()
+                .map(|(day, names)| {
+                    Ok(Date {
+                        value: day.to_string(),
+                        currencies: currencies(names),
+                    })
+                })
+                .collect::<Vec<Result<Date, Error>>>();
+            Box::pin(stream::iter(dates))
+        };
+        let newer = dates(&[
+            ("2019-10-21", &["USD"][..]),
+            ("2019-10-18", &["HRK", "USD"][..]),
+        ]);
+        store_dates(&db, newer, None).await.unwrap();
+        // spans are widened by older dates stored later, e.g. on an import
+        let older = dates(&[("2019-10-17", &["HRK", "USD"][..])]);
+        store_dates(&db, older, None).await.unwrap();
+
+        let spans = db.currency_spans().await.unwrap();
+        assert_eq!(spans.len(), 3);
+        assert_eq!(
+            spans["HRK"],
+            ("2019-10-17".to_string(), "2019-10-18".to_string())
+        );
+        assert_eq!(
+            spans["USD"],
+            ("2019-10-17".to_string(), "2019-10-21".to_string())
+        );
+        assert_eq!(spans, db.scan_spans().await.unwrap());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn bootstrap_new_from_mock() {
+        let mock = MockEcb::start(mock_ecb::seed_dates()).await;
+        let source = Ecb::new(mock.base_url());
+        let dir = tempdir().unwrap();
+        let db = bootstrap_new(dir.path().join("db"), &source).await.unwrap();
+
+        let current = db.get_current_rates().await.unwrap();
+        assert_eq!(current.value, "2019-10-18");
+        assert!(current.currencies.iter().any(|c| c.name == "EUR"));
+        assert_eq!(stored_days(&db).await, mock_ecb::seed_dates().len());
+        assert_eq!(mock.hits(ECB_HIST), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn bootstrap_and_import_from_file() {
+        let dir = tempdir().unwrap();
+        let older = dir.path().join("older.xml");
+        std::fs::write(&older, mock_ecb::envelope(&mock_ecb::seed_dates()[5..])).unwrap();
+        let db = init(dir.path().join("db"), &LocalFile::new(&older))
+            .await
+            .unwrap();
+        assert_eq!(db.get_current_rates().await.unwrap().value, "2019-10-11");
+
+        import(&db, &LocalFile::new("seed_rates.xml"))
+            .await
+            .unwrap();
+        assert_eq!(db.get_current_rates().await.unwrap().value, "2019-10-18");
+        assert_eq!(stored_days(&db).await, mock_ecb::seed_dates().len());
+
+        // older rates don't move the current ones back
+        import(&db, &LocalFile::new(&older)).await.unwrap();
+        assert_eq!(db.get_current_rates().await.unwrap().value, "2019-10-18");
+
+        // a day stored with other rates refuses the whole import
+        let mut dates = mock_ecb::seed_dates()[..1].to_vec();
+        let mut newer = dates[0].clone();
+        newer.value = "2019-10-21".to_string();
+        dates[0].currencies[0].rate += Decimal::ONE;
+        dates.insert(0, newer);
+        let conflict = dir.path().join("conflict.xml");
+        std::fs::write(&conflict, mock_ecb::envelope(&dates)).unwrap();
+        assert!(matches!(
+            import(&db, &LocalFile::new(&conflict)).await,
+            Err(Error::Import(_))
+        ));
+        assert_eq!(db.get_current_rates().await.unwrap().value, "2019-10-18");
+        assert_eq!(stored_days(&db).await, mock_ecb::seed_dates().len());
+        let stored = db.get_day_rates("2019-10-18").await.unwrap().unwrap();
+        assert_eq!(
+            stored.currencies[0].rate,
+            mock_ecb::seed_dates()[0].currencies[0].rate
+        );
+
+        // so does a missing day before the current rates, past responses are cached as immutable
+        let mut dates = mock_ecb::seed_dates()[..1].to_vec();
+        let mut missing = dates[0].clone();
+        missing.value = "2019-09-02".to_string();
+        dates[0].value = "2019-10-21".to_string();
+        dates.push(missing);
+        let gap = dir.path().join("gap.xml");
+        std::fs::write(&gap, mock_ecb::envelope(&dates)).unwrap();
+        assert!(matches!(
+            import(&db, &LocalFile::new(&gap)).await,
+            Err(Error::Import(_))
+        ));
+        assert_eq!(db.get_current_rates().await.unwrap().value, "2019-10-18");
+        assert_eq!(db.get_day_rates("2019-10-21").await.unwrap(), None);
+
+        // newer dates are added
+        let newer = dir.path().join("newer.xml");
+        std::fs::write(&newer, mock_ecb::envelope(&dates[..1])).unwrap();
+        import(&db, &LocalFile::new(&newer)).await.unwrap();
+        assert_eq!(db.get_current_rates().await.unwrap().value, "2019-10-21");
+        assert_eq!(db.currency_spans().await.unwrap()["USD"].1, "2019-10-21");
+
+        let empty = dir.path().join("empty.xml");
+        std::fs::write(&empty, mock_ecb::envelope(&[])).unwrap();
+        assert!(matches!(
+            import(&db, &LocalFile::new(&empty)).await,
+            Err(Error::Import(_))
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn update_up_to_date() {
+        let mock = MockEcb::start(mock_ecb::seed_dates()).await;
+        let source = Ecb::new(mock.base_url());
+        let dir = tempdir().unwrap();
+        let db = bootstrap_new(dir.path().join("db"), &source).await.unwrap();
+
+        update(&db, &source).await.unwrap();
+        assert_eq!(mock.hits(ECB_DAILY), 1);
+        assert_eq!(mock.hits(ECB_HIST_LAST_90), 0);
+        assert_eq!(mock.hits(ECB_HIST), 1);
+        assert_eq!(stored_days(&db).await, mock_ecb::seed_dates().len());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn update_not_modified() {
+        let mock = MockEcb::start(seed_dates_until("2019-10-17")).await;
+        let source = Ecb::new(mock.base_url());
+        let dir = tempdir().unwrap();
+        let db = bootstrap_new(dir.path().join("db"), &source).await.unwrap();
+
+        update(&db, &source).await.unwrap();
+        update(&db, &source).await.unwrap();
+        assert_eq!(mock.hits(ECB_DAILY), 2);
+        assert_eq!(mock.not_modified(ECB_DAILY), 1);
+        let validators = db.get_validators(&source.url(ECB_DAILY)).await.unwrap();
+        assert!(validators.unwrap().etag.is_some());
+
+        mock.set_dates(mock_ecb::seed_dates());
+        update(&db, &source).await.unwrap();
+        assert_eq!(mock.not_modified(ECB_DAILY), 1);
+        let current = db.get_current_rates().await.unwrap();
+        assert_eq!(current.value, "2019-10-18");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn update_one_day_gap() {
+        let mock = MockEcb::start(seed_dates_until("2019-10-17")).await;
+        let source = Ecb::new(mock.base_url());
+        let dir = tempdir().unwrap();
+        let db = bootstrap_new(dir.path().join("db"), &source).await.unwrap();
+
+        mock.set_dates(mock_ecb::seed_dates());
+        update(&db, &source).await.unwrap();
+
+        let current = db.get_current_rates().await.unwrap();
+        assert_eq!(current.value, "2019-10-18");
+        assert_eq!(mock.hits(ECB_DAILY), 1);
+        assert_eq!(mock.hits(ECB_HIST_LAST_90), 0);
+        assert_eq!(mock.hits(ECB_HIST), 1);
+        assert_eq!(stored_days(&db).await, mock_ecb::seed_dates().len());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn update_last_90_days_gap() {
+        let mock = MockEcb::start(seed_dates_until("2019-09-30")).await;
+        let source = Ecb::new(mock.base_url());
+        let dir = tempdir().unwrap();
+        let db = bootstrap_new(dir.path().join("db"), &source).await.unwrap();
+
+        mock.set_dates(mock_ecb::seed_dates());
+        update(&db, &source).await.unwrap();
+
+        let current = db.get_current_rates().await.unwrap();
+        assert_eq!(current.value, "2019-10-18");
+        assert_eq!(mock.hits(ECB_HIST_LAST_90), 1);
+        assert_eq!(mock.hits(ECB_HIST), 1);
+        assert_eq!(stored_days(&db).await, mock_ecb::seed_dates().len());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn update_history_gap() {
+        let mut old = mock_ecb::seed_dates().pop().unwrap();
+        old.value = "2019-03-01".to_string();
+        let mock = MockEcb::start(vec![old.clone()]).await;
+        let source = Ecb::new(mock.base_url());
+        let dir = tempdir().unwrap();
+        let db = bootstrap_new(dir.path().join("db"), &source).await.unwrap();
+
+        let mut dates = mock_ecb::seed_dates();
+        dates.push(old);
+        mock.set_dates(dates);
+        update(&db, &source).await.unwrap();
+
+        let current = db.get_current_rates().await.unwrap();
+        assert_eq!(current.value, "2019-10-18");
+        assert_eq!(mock.hits(ECB_HIST_LAST_90), 0);
+        assert_eq!(mock.hits(ECB_HIST), 2);
+        assert_eq!(stored_days(&db).await, mock_ecb::seed_dates().len() + 1);
+    }
+
+    // sled's flusher thread releases the file lock shortly after the last handle is dropped, so
+    // opening the same path again right away can fail
+    async fn reopen(path: &Path) -> Db {
+        let mut attempts = 0;
+        loop {
+            match init(path, &Ecb::new("http://127.0.0.1:9")).await {
+                Ok(db) => return db,
+                Err(_) if attempts < 100 => {
+                    attempts += 1;
+                    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+                }
+                Err(err) => panic!("could not reopen the database, {}", err),
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn migrate_legacy_rates() {
+        #[derive(Serialize)]
+        struct LegacyDate {
+            value: String,
+            currencies: Vec<(String, f64)>,
+        }
+
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("db");
+        let db = Db::open(&path).unwrap();
+        let legacy = LegacyDate {
+            value: "2019-10-18".to_string(),
+            currencies: vec![
+                ("USD".to_string(), 1.1144),
+                ("IDR".to_string(), 15773.15),
+                ("EUR".to_string(), 1.0),
+            ],
+        };
+        let key = date_as_key(&legacy.value).unwrap();
+        db.put(&key, &legacy).await.unwrap();
+        db.put(b"current", &key).await.unwrap();
+        db.inner.flush_async().await.unwrap();
+        drop(db);
+
+        let db = reopen(&path).await;
+        let current = db.get_current_rates().await.unwrap();
+        let rates = current
+            .currencies
+            .iter()
+            .map(|currency| (currency.name.as_str(), currency.rate.to_string()))
+            .collect::<Vec<(&str, String)>>();
+        assert_eq!(
+            rates,
+            vec![
+                ("USD", "1.1144".into()),
+                ("IDR", "15773.15".into()),
+                ("EUR", "1".into())
+            ]
+        );
+        assert_eq!(db.get::<u32>(b"version").await.unwrap(), Some(VERSION));
+        assert_eq!(
+            db.currency_spans().await.unwrap()["IDR"],
+            ("2019-10-18".to_string(), "2019-10-18".to_string())
+        );
+
+        // the decimals are written along with version 1, a migration interrupted after them
+        // doesn't read them as legacy rates again
+        db.put(b"version", &1u32).await.unwrap();
+        db.migrate().await.unwrap();
+        assert_eq!(db.get_current_rates().await.unwrap(), current);
+    }
 }
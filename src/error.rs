@@ -19,6 +19,19 @@ pub async fn recover(err: Rejection) -> Result<impl Reply, Rejection> {
             | Error::InvalidSymbol
             | Error::MissingDateBoundaries
             | Error::InvalidDateRange
+            | Error::MissingParameter(_)
+            | Error::InvalidAmount(_)
+            | Error::InvalidFallback(_)
+            | Error::InvalidFormat(_)
+            | Error::InvalidPrecision(_)
+            | Error::InvalidRounding(_)
+            | Error::InvalidGroup(_)
+            | Error::InvalidAggregation(_)
+            | Error::InvalidStrict(_)
+            | Error::MissingRates(_)
+            | Error::InvalidBatch(_)
+            // from the requested amounts and ranges
+            | Error::Overflow(_)
             | Error::InvalidBase(_) => {
                 log::trace!("api reject, {}", err);
                 ErrorMessage {
@@ -59,8 +72,10 @@ pub enum Error {
     DateParse(String, #[source] chrono::ParseError),
     #[error("`{0}` is invalid, there are no currency rates for dates older then 1999-01-04.")]
     PastDate(&'static str),
-    #[error("`{0}` is an invalid port")]
-    InvalidPort(String, #[source] std::num::ParseIntError),
+    #[error("invalid CORS configuration, {0}")]
+    InvalidCors(String),
+    #[error("invalid configuration, {0}")]
+    Config(String),
     #[error("start_at must be older than end_at")]
     InvalidDateRange,
     #[error("`{0}`: `{1}` is in an invalid date format, date must be in the format %Y-%m-%d")]
@@ -73,12 +88,115 @@ pub enum Error {
     InvalidSymbol,
     #[error("both start_at and end_at parameters must be present")]
     MissingDateBoundaries,
+    #[error("`{0}` parameter must be present")]
+    MissingParameter(&'static str),
+    #[error("`{0}` is an invalid amount")]
+    InvalidAmount(String),
+    #[error("`{0}` is an invalid fallback, must be one of previous, next or none")]
+    InvalidFallback(String),
+    #[error("`{0}` is an invalid format, must be one of json or csv")]
+    InvalidFormat(String),
+    #[error("`{0}` is an invalid precision, must be a number of decimal places up to 28")]
+    InvalidPrecision(String),
+    #[error("`{0}` is an invalid rounding, must be one of half_even, half_up, half_down, up, down, ceiling or floor")]
+    InvalidRounding(String),
+    #[error("`{0}` is an invalid group, must be one of week, month, quarter or year")]
+    InvalidGroup(String),
+    #[error("`{0}` is an invalid aggregation, must be one of last, first, mean, min or max")]
+    InvalidAggregation(String),
+    #[error("`{0}` is an invalid strict, must be one of true or false")]
+    InvalidStrict(String),
+    #[error("missing rates for {0}")]
+    MissingRates(String),
+    #[error("invalid batch of transactions, {0}")]
+    InvalidBatch(String),
+    #[error("overflow computing `{0}`")]
+    Overflow(&'static str),
     #[error("database error, `{0}`")]
     Database(String, #[source] Option<Box<dyn StdError + Sync + Send>>),
     #[error("error fetching currencies from ECB, `{0}`")]
     Fetcher(String),
+    #[error("error importing rates, `{0}`")]
+    Import(String),
+    #[error("updater task failed, `{0}`")]
+    Updater(String),
+    #[error("error encoding metrics, `{0}`")]
+    Metrics(String),
+    #[error("error compressing response, `{0}`")]
+    Compression(String),
+    #[error("error writing csv, `{0}`")]
+    Csv(#[source] csv::Error),
     #[error("error rendering template, `{0}`")]
     Template(#[source] askama::Error),
 }
 
+impl Error {
+    // name of the variant, used to label metrics
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::DateNotFound(_) => "date_not_found",
+            Error::DateParse(_, _) => "date_parse",
+            Error::PastDate(_) => "past_date",
+            Error::InvalidCors(_) => "invalid_cors",
+            Error::Config(_) => "config",
+            Error::InvalidDateRange => "invalid_date_range",
+            Error::InvalidDateFormat(_, _) => "invalid_date_format",
+            Error::InvalidBase(_) => "invalid_base",
+            Error::EmpyDataset => "empty_dataset",
+            Error::InvalidSymbol => "invalid_symbol",
+            Error::MissingDateBoundaries => "missing_date_boundaries",
+            Error::MissingParameter(_) => "missing_parameter",
+            Error::InvalidAmount(_) => "invalid_amount",
+            Error::InvalidFallback(_) => "invalid_fallback",
+            Error::InvalidFormat(_) => "invalid_format",
+            Error::InvalidPrecision(_) => "invalid_precision",
+            Error::InvalidRounding(_) => "invalid_rounding",
+            Error::InvalidGroup(_) => "invalid_group",
+            Error::InvalidAggregation(_) => "invalid_aggregation",
+            Error::InvalidStrict(_) => "invalid_strict",
+            Error::MissingRates(_) => "missing_rates",
+            Error::InvalidBatch(_) => "invalid_batch",
+            Error::Overflow(_) => "overflow",
+            Error::Database(_, _) => "database",
+            Error::Fetcher(_) => "fetcher",
+            Error::Import(_) => "import",
+            Error::Updater(_) => "updater",
+            Error::Metrics(_) => "metrics",
+            Error::Compression(_) => "compression",
+            Error::Csv(_) => "csv",
+            Error::Template(_) => "template",
+        }
+    }
+}
+
 impl warp::reject::Reject for Error {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn status(err: Error) -> StatusCode {
+        super::recover(warp::reject::custom(err))
+            .await
+            .unwrap()
+            .into_response()
+            .status()
+    }
+
+    #[tokio::test]
+    async fn recover() {
+        assert_eq!(status(Error::InvalidSymbol).await, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            status(Error::Overflow("mean")).await,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            status(Error::DateNotFound("2019-10-19".into())).await,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            status(Error::Database("could not open".into(), None)).await,
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+}
@@ -1,20 +1,41 @@
 use crate::error::Error;
+use async_trait::async_trait;
 use chrono::NaiveDate;
-use hyper::Client;
+use futures::Stream;
+use hyper::body::{Bytes, HttpBody};
+use hyper::header::{self, HeaderMap, HeaderName};
+use hyper::{Client, Request, StatusCode};
 use hyper_rustls::HttpsConnectorBuilder;
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
+use std::cmp;
+use std::io::{self, BufReader, Read};
+use std::pin::Pin;
 use std::str::FromStr;
+use tokio::runtime::Handle;
+use tokio_stream::wrappers::ReceiverStream;
+use xml::reader::{EventReader, XmlEvent};
 
-const ECB_DAILY: &str = "https://www.ecb.europa.eu/stats/eurofxref/eurofxref-daily.xml";
-const ECB_HIST: &str = "https://www.ecb.europa.eu/stats/eurofxref/eurofxref-hist.xml";
-const ECB_HIST_LAST_90: &str = "https://www.ecb.europa.eu/stats/eurofxref/eurofxref-hist-90d.xml";
+pub const ECB_URL: &str = "https://www.ecb.europa.eu/stats/eurofxref";
+pub const ECB_DAILY: &str = "eurofxref-daily.xml";
+pub const ECB_HIST: &str = "eurofxref-hist.xml";
+pub const ECB_HIST_LAST_90: &str = "eurofxref-hist-90d.xml";
 
+// number of parsed dates buffered ahead of the consumer
+const DATES_BUFFER: usize = 64;
+
+// dates parsed as they are downloaded
+pub type DateStream = Pin<Box<dyn Stream<Item = Result<Date, Error>> + Send>>;
+
+// whole document deserialization, used on tests to load fixtures
+#[cfg(test)]
 #[derive(Debug, Deserialize)]
 pub struct Envelope {
     #[serde(rename = "Cube", default)]
     pub cube: Cube,
 }
 
+#[cfg(test)]
 #[derive(Debug, Deserialize, Default)]
 pub struct Cube {
     #[serde(rename = "Cube", default)]
@@ -44,55 +65,388 @@ impl Date {
 pub struct Currency {
     #[serde(rename = "currency", default)]
     pub name: String,
-    pub rate: f64,
+    // exactly as published
+    pub rate: Decimal,
+}
+
+// `ETag` and `Last-Modified` of a fetched document, sent back on the next request for it so that
+// an unchanged document is not downloaded again
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+pub enum Fetched {
+    // the document didn't change since the request's validators were received
+    NotModified,
+    Modified {
+        dates: DateStream,
+        validators: Validators,
+    },
+}
+
+// a provider of reference rates, dates are streamed newest first
+#[async_trait]
+pub trait RateSource: Send + Sync {
+    // location of one of the source's documents, e.g. ECB_DAILY
+    fn url(&self, document: &str) -> String;
+
+    // reference rates of the last published day, unless unchanged since `validators`
+    async fn daily(&self, validators: &Validators) -> Result<Fetched, Error>;
+
+    // reference rates of the last 90 days
+    async fn last90(&self) -> Result<DateStream, Error>;
+
+    // all the historical reference rates
+    async fn history(&self) -> Result<DateStream, Error>;
+}
+
+// European Central Bank's euro foreign exchange reference rates
+#[derive(Clone, Debug)]
+pub struct Ecb {
+    base_url: String,
 }
 
-pub async fn fetch_last90() -> Result<Vec<Date>, Error> {
-    fetch(ECB_HIST_LAST_90).await
+impl Ecb {
+    // base_url is the location of the ECB's xml files, useful to point to a mirror
+    pub fn new<S: Into<String>>(base_url: S) -> Ecb {
+        Ecb {
+            base_url: base_url.into(),
+        }
+    }
 }
 
-pub async fn fetch_hist() -> Result<Vec<Date>, Error> {
-    fetch(ECB_HIST).await
+#[async_trait]
+impl RateSource for Ecb {
+    fn url(&self, document: &str) -> String {
+        format!("{}/{}", self.base_url.trim_end_matches('/'), document)
+    }
+
+    async fn daily(&self, validators: &Validators) -> Result<Fetched, Error> {
+        fetch_if_modified(&self.url(ECB_DAILY), validators).await
+    }
+
+    async fn last90(&self) -> Result<DateStream, Error> {
+        fetch(&self.url(ECB_HIST_LAST_90)).await
+    }
+
+    async fn history(&self) -> Result<DateStream, Error> {
+        fetch(&self.url(ECB_HIST)).await
+    }
 }
 
-pub async fn fetch_daily() -> Result<Date, Error> {
-    let mut dates = fetch(ECB_DAILY).await?;
-    let dates = dates
-        .pop()
-        .ok_or_else(|| Error::Fetcher("Daily rates are empty".into()))?;
-    Ok(dates)
+pub async fn fetch(url: &str) -> Result<DateStream, Error> {
+    match fetch_if_modified(url, &Validators::default()).await? {
+        Fetched::Modified { dates, .. } => Ok(dates),
+        Fetched::NotModified => Err(Error::Fetcher(format!(
+            "{} responded not modified to an unconditional request",
+            url
+        ))),
+    }
 }
 
-pub async fn fetch(url: &str) -> Result<Vec<Date>, Error> {
+// conditional request for the document at `url`, with the validators of a previous response
+pub async fn fetch_if_modified(url: &str, validators: &Validators) -> Result<Fetched, Error> {
     let https = HttpsConnectorBuilder::new()
         .with_webpki_roots()
-        .https_only()
+        .https_or_http()
         .enable_http1()
         .build();
     let client: Client<_, hyper::Body> = Client::builder().build(https);
+    let uri = url
+        .parse::<hyper::Uri>()
+        .map_err(|err| Error::Fetcher(format!("could not parse url: {}, {}", url, err)))?;
+    let mut req = Request::get(uri);
+    if let Some(ref etag) = validators.etag {
+        req = req.header(header::IF_NONE_MATCH, etag);
+    }
+    if let Some(ref last_modified) = validators.last_modified {
+        req = req.header(header::IF_MODIFIED_SINCE, last_modified);
+    }
+    let req = req
+        .body(hyper::Body::empty())
+        .map_err(|err| Error::Fetcher(format!("could not build request to {}, {}", url, err)))?;
     let res = client
-        .get(
-            url.parse::<hyper::Uri>()
-                .map_err(|err| Error::Fetcher(format!("could not parse url: {}, {}", url, err)))?,
-        )
-        .await
-        .map_err(|err| Error::Fetcher(err.to_string()))?;
-    let body = hyper::body::to_bytes(res.into_body())
+        .request(req)
         .await
         .map_err(|err| Error::Fetcher(err.to_string()))?;
-    let envelope: Envelope =
-        serde_xml_rs::from_reader(body.as_ref()).map_err(|err| Error::Fetcher(err.to_string()))?;
-    Ok(envelope.cube.dates)
+    if res.status() == StatusCode::NOT_MODIFIED {
+        return Ok(Fetched::NotModified);
+    }
+    if !res.status().is_success() {
+        return Err(Error::Fetcher(format!(
+            "{} responded with {}",
+            url,
+            res.status()
+        )));
+    }
+
+    let validators = Validators {
+        etag: header_value(res.headers(), header::ETAG),
+        last_modified: header_value(res.headers(), header::LAST_MODIFIED),
+    };
+    let reader = BodyReader {
+        body: res.into_body(),
+        handle: Handle::current(),
+        chunk: Bytes::new(),
+    };
+    Ok(Fetched::Modified {
+        dates: parse(BufReader::new(reader)),
+        validators,
+    })
+}
+
+fn header_value(headers: &HeaderMap, name: HeaderName) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(String::from)
+}
+
+// parse the dates of an eurofxref xml document as it is read
+pub fn parse<R: Read + Send + 'static>(reader: R) -> DateStream {
+    stream_blocking(move || DateParser::new(reader))
+}
+
+// stream the dates of a synchronous parser, run on a blocking thread, dropping the stream stops it
+pub fn stream_blocking<F, I>(dates: F) -> DateStream
+where
+    F: FnOnce() -> I + Send + 'static,
+    I: Iterator<Item = Result<Date, Error>>,
+{
+    send_blocking(move |send| {
+        for date in dates() {
+            if !send(date) {
+                break;
+            }
+        }
+    })
+}
+
+// stream the dates `send_dates` sends from a blocking thread, for parsers borrowing from it like
+// the ones reading a zip entry. `send` returns false once the stream is dropped.
+pub fn send_blocking<F>(send_dates: F) -> DateStream
+where
+    F: FnOnce(&mut dyn FnMut(Result<Date, Error>) -> bool) + Send + 'static,
+{
+    let (tx, rx) = tokio::sync::mpsc::channel(DATES_BUFFER);
+    tokio::task::spawn_blocking(move || {
+        send_dates(&mut |date| tx.blocking_send(date).is_ok());
+    });
+    Box::pin(ReceiverStream::new(rx))
+}
+
+// blocking reader over a response body, waiting on the runtime for each chunk
+struct BodyReader {
+    body: hyper::Body,
+    handle: Handle,
+    chunk: Bytes,
+}
+
+impl Read for BodyReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.chunk.is_empty() {
+            match self.handle.block_on(self.body.data()) {
+                Some(Ok(chunk)) => self.chunk = chunk,
+                Some(Err(err)) => return Err(io::Error::other(err)),
+                None => return Ok(0),
+            }
+        }
+        let len = cmp::min(buf.len(), self.chunk.len());
+        buf[..len].copy_from_slice(&self.chunk.split_to(len));
+        Ok(len)
+    }
+}
+
+// pull parser yielding the dates of an eurofxref xml document one at a time, dates are the
+// `<Cube time="..">` elements and their rates the `<Cube currency=".." rate="..">` inside them
+pub struct DateParser<R: Read> {
+    events: EventReader<R>,
+    // depth of the current `Cube` element
+    depth: usize,
+    // date being parsed and its depth
+    date: Option<(usize, Date)>,
+    done: bool,
+}
+
+impl<R: Read> DateParser<R> {
+    pub fn new(reader: R) -> DateParser<R> {
+        DateParser {
+            events: EventReader::new(reader),
+            depth: 0,
+            date: None,
+            done: false,
+        }
+    }
+
+    fn fail(&mut self, msg: String) -> Option<Result<Date, Error>> {
+        self.done = true;
+        Some(Err(Error::Fetcher(msg)))
+    }
+}
+
+impl<R: Read> Iterator for DateParser<R> {
+    type Item = Result<Date, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            let event = match self.events.next() {
+                Ok(event) => event,
+                Err(err) => return self.fail(format!("could not parse rates xml, {}", err)),
+            };
+
+            match event {
+                XmlEvent::StartElement {
+                    name, attributes, ..
+                } if name.local_name == "Cube" => {
+                    self.depth += 1;
+                    let attribute = |key: &str| {
+                        attributes
+                            .iter()
+                            .find(|attribute| attribute.name.local_name == key)
+                            .map(|attribute| attribute.value.as_str())
+                    };
+
+                    if let Some(time) = attribute("time") {
+                        let date = Date {
+                            value: time.to_string(),
+                            currencies: Vec::new(),
+                        };
+                        self.date = Some((self.depth, date));
+                    } else if let (Some(name), Some(rate)) =
+                        (attribute("currency"), attribute("rate"))
+                    {
+                        let currency = match Decimal::from_str(rate) {
+                            Ok(rate) => Currency {
+                                name: name.to_string(),
+                                rate,
+                            },
+                            Err(err) => {
+                                let msg =
+                                    format!("could not parse {} rate {}, {}", name, rate, err);
+                                return self.fail(msg);
+                            }
+                        };
+                        if let Some((_, ref mut date)) = self.date {
+                            date.currencies.push(currency);
+                        }
+                    }
+                }
+                XmlEvent::EndElement { name } if name.local_name == "Cube" => {
+                    let closes_date = matches!(self.date, Some((depth, _)) if depth == self.depth);
+                    self.depth = self.depth.saturating_sub(1);
+                    if closes_date {
+                        return self.date.take().map(|(_, date)| Ok(date));
+                    }
+                }
+                XmlEvent::EndDocument => self.done = true,
+                _ => (),
+            }
+        }
+        None
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::mock_ecb::{self, MockEcb};
+
+    use futures::TryStreamExt;
+
+    // reads a few bytes at a time, like a slow download
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = cmp::min(cmp::min(buf.len(), 7), self.0.len());
+            buf[..len].copy_from_slice(&self.0[..len]);
+            self.0 = &self.0[len..];
+            Ok(len)
+        }
+    }
 
     #[tokio::test]
     async fn fetch() {
-        let current = fetch_daily().await.unwrap();
-        current.value_as_date().unwrap();
+        let mock = MockEcb::start(mock_ecb::seed_dates()).await;
+        let source = Ecb::new(mock.base_url());
+
+        let dates = source
+            .last90()
+            .await
+            .unwrap()
+            .try_collect::<Vec<Date>>()
+            .await
+            .unwrap();
+        assert_eq!(dates.len(), mock_ecb::seed_dates().len());
+        assert_eq!(dates[0].value_as_date().unwrap().to_string(), "2019-10-18");
+
+        let dates = match source.daily(&Validators::default()).await.unwrap() {
+            Fetched::Modified { dates, .. } => dates.try_collect::<Vec<Date>>().await.unwrap(),
+            Fetched::NotModified => panic!("unconditional request not modified"),
+        };
+        assert_eq!(dates.len(), 1);
+        assert_eq!(dates[0].value_as_date().unwrap().to_string(), "2019-10-18");
+
+        let dates = source
+            .history()
+            .await
+            .unwrap()
+            .try_collect::<Vec<Date>>()
+            .await
+            .unwrap();
+        assert_eq!(dates, mock_ecb::seed_dates());
+
+        let source = Ecb::new(format!("{}/missing", mock.base_url()));
+        assert!(source.history().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn fetch_if_modified() {
+        let mock = MockEcb::start(mock_ecb::seed_dates()).await;
+        let source = Ecb::new(mock.base_url());
+
+        let validators = match source.daily(&Validators::default()).await.unwrap() {
+            Fetched::Modified { validators, .. } => validators,
+            Fetched::NotModified => panic!("unconditional request not modified"),
+        };
+        assert!(validators.etag.is_some());
+        assert!(validators.last_modified.is_some());
+
+        let fetched = source.daily(&validators).await.unwrap();
+        assert!(matches!(fetched, Fetched::NotModified));
+        // either validator is enough
+        let last_modified = Validators {
+            etag: None,
+            ..validators.clone()
+        };
+        let fetched = source.daily(&last_modified).await.unwrap();
+        assert!(matches!(fetched, Fetched::NotModified));
+
+        mock.set_dates(mock_ecb::seed_dates().split_off(1));
+        let fetched = source.daily(&validators).await.unwrap();
+        assert!(matches!(fetched, Fetched::Modified { .. }));
+    }
+
+    #[test]
+    fn date_parser() {
+        let xml = mock_ecb::envelope(&mock_ecb::seed_dates());
+        let dates = DateParser::new(Trickle(xml.as_bytes()))
+            .collect::<Result<Vec<Date>, Error>>()
+            .unwrap();
+        assert_eq!(dates, mock_ecb::seed_dates());
+
+        let xml = r#"<Envelope><Cube><Cube time="2019-10-18"><Cube currency="USD" rate="1,1"/></Cube></Cube></Envelope>"#;
+        let mut parser = DateParser::new(xml.as_bytes());
+        assert!(matches!(parser.next(), Some(Err(Error::Fetcher(_)))));
+        assert!(parser.next().is_none());
+
+        // truncated download
+        let xml = mock_ecb::envelope(&mock_ecb::seed_dates());
+        let dates = DateParser::new(&xml.as_bytes()[..xml.len() / 2]).collect::<Vec<_>>();
+        assert!(dates.iter().take(dates.len() - 1).all(Result::is_ok));
+        assert!(dates.last().unwrap().is_err());
     }
 
     #[test]
@@ -47,41 +47,43 @@ pub async fn index(db: Arc<Db>) -> Result<impl warp::Reply, warp::Rejection> {
 #[cfg(test)]
 mod tests {
     use super::Currency;
+    use rust_decimal::Decimal;
 
     #[test]
+    #[allow(clippy::vec_init_then_push)]
     fn sort_currencies() {
         let mut currencies = Vec::new();
         currencies.push(Currency {
             name: "JPY".to_string(),
-            rate: 0.0,
+            rate: Decimal::ZERO,
         });
         currencies.push(Currency {
             name: "RON".to_string(),
-            rate: 0.0,
+            rate: Decimal::ZERO,
         });
         currencies.push(Currency {
             name: "USD".to_string(),
-            rate: 0.0,
+            rate: Decimal::ZERO,
         });
         currencies.push(Currency {
             name: "CZK".to_string(),
-            rate: 0.0,
+            rate: Decimal::ZERO,
         });
         currencies.push(Currency {
             name: "GBP".to_string(),
-            rate: 0.0,
+            rate: Decimal::ZERO,
         });
         currencies.push(Currency {
             name: "CHF".to_string(),
-            rate: 0.0,
+            rate: Decimal::ZERO,
         });
         currencies.push(Currency {
             name: "EUR".to_string(),
-            rate: 0.0,
+            rate: Decimal::ZERO,
         });
         currencies.push(Currency {
             name: "RUB".to_string(),
-            rate: 0.0,
+            rate: Decimal::ZERO,
         });
         super::sort_currencies(&mut currencies);
         assert_eq!(&currencies[0].name, "EUR");
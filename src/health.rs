@@ -0,0 +1,195 @@
+use std::sync::Arc;
+
+use chrono::{Datelike, NaiveDate, Utc, Weekday};
+use serde::Serialize;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+
+use crate::db::Db;
+use crate::updater::{Status, StatusHandle};
+
+// ECB doesn't publish rates on weekends and TARGET closing days, the longest run of closing days
+// on business days is Good Friday and Easter Monday, so being more than 3 business days behind
+// means the data has gone stale. Without an updater, in no-fetch mode, rates only change through
+// imports and are never considered stale
+pub const MAX_BUSINESS_DAYS_BEHIND: i64 = 3;
+
+#[derive(Debug, Serialize)]
+struct Health {
+    database: &'static str,
+    current: Option<String>,
+    business_days_behind: Option<i64>,
+    max_business_days_behind: Option<i64>,
+    stale: bool,
+    updater: Status,
+}
+
+pub fn routes(
+    db: Arc<Db>,
+    updater: StatusHandle,
+    max_business_days_behind: Option<i64>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let health =
+        warp::any().and_then(move || {
+            let db = db.clone();
+            let updater = updater.clone();
+            async move {
+                Ok::<_, Rejection>(health(&db, &updater, max_business_days_behind, today()).await)
+            }
+        });
+
+    // the process is up and the database can be read
+    let healthz = warp::path("healthz")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(health.clone())
+        .map(|health: Health| {
+            let status = match health.database {
+                "open" => StatusCode::OK,
+                _ => StatusCode::SERVICE_UNAVAILABLE,
+            };
+            warp::reply::with_status(warp::reply::json(&health), status)
+        });
+
+    // the database can be read and its rates are fresh, so traffic can be routed here
+    let readyz = warp::path("readyz")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(health)
+        .map(|health: Health| {
+            let status = match (health.database, health.stale) {
+                ("open", false) => StatusCode::OK,
+                _ => StatusCode::SERVICE_UNAVAILABLE,
+            };
+            warp::reply::with_status(warp::reply::json(&health), status)
+        });
+
+    healthz.or(readyz)
+}
+
+fn today() -> NaiveDate {
+    Utc::now().naive_utc().date()
+}
+
+async fn health(
+    db: &Db,
+    updater: &StatusHandle,
+    max_business_days_behind: Option<i64>,
+    today: NaiveDate,
+) -> Health {
+    let current = match db.get_current_rates().await {
+        Ok(current) => current,
+        Err(err) => {
+            log::error!("health check could not read current rates, {}", err);
+            return Health {
+                database: "unavailable",
+                current: None,
+                business_days_behind: None,
+                max_business_days_behind,
+                stale: true,
+                updater: updater.get(),
+            };
+        }
+    };
+
+    let behind = current
+        .value_as_date()
+        .map(|date| business_days_between(date, today))
+        .ok();
+
+    Health {
+        database: "open",
+        current: Some(current.value),
+        business_days_behind: behind,
+        max_business_days_behind,
+        stale: max_business_days_behind
+            .is_some_and(|max| !matches!(behind, Some(behind) if behind <= max)),
+        updater: updater.get(),
+    }
+}
+
+// number of weekdays after `from` up to and including `to`
+fn business_days_between(from: NaiveDate, to: NaiveDate) -> i64 {
+    from.iter_days()
+        .skip(1)
+        .take_while(|day| day <= &to)
+        .filter(|day| !matches!(day.weekday(), Weekday::Sat | Weekday::Sun))
+        .count() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_ecb;
+
+    #[test]
+    fn business_days_between() {
+        let friday = NaiveDate::from_ymd(2019, 10, 18);
+        assert_eq!(super::business_days_between(friday, friday), 0);
+        // weekend
+        let sunday = NaiveDate::from_ymd(2019, 10, 20);
+        assert_eq!(super::business_days_between(friday, sunday), 0);
+        let tuesday = NaiveDate::from_ymd(2019, 10, 22);
+        assert_eq!(super::business_days_between(friday, tuesday), 2);
+        let next_friday = NaiveDate::from_ymd(2019, 10, 25);
+        assert_eq!(super::business_days_between(friday, next_friday), 5);
+        // future dates are never behind
+        assert_eq!(super::business_days_between(tuesday, friday), 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn health() {
+        let seeded = mock_ecb::seeded_db().await;
+        let updater = StatusHandle::default();
+
+        let health = super::health(
+            &seeded.db,
+            &updater,
+            Some(MAX_BUSINESS_DAYS_BEHIND),
+            NaiveDate::from_ymd(2019, 10, 22),
+        )
+        .await;
+        assert_eq!(health.database, "open");
+        assert_eq!(health.current.as_deref(), Some("2019-10-18"));
+        assert_eq!(health.business_days_behind, Some(2));
+        assert!(!health.stale);
+
+        let health = super::health(
+            &seeded.db,
+            &updater,
+            Some(MAX_BUSINESS_DAYS_BEHIND),
+            NaiveDate::from_ymd(2019, 10, 24),
+        )
+        .await;
+        assert_eq!(health.business_days_behind, Some(4));
+        assert!(health.stale);
+
+        // no-fetch mode
+        let health = super::health(
+            &seeded.db,
+            &updater,
+            None,
+            NaiveDate::from_ymd(2019, 10, 24),
+        )
+        .await;
+        assert_eq!(health.business_days_behind, Some(4));
+        assert!(!health.stale);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn readyz_stale() {
+        let seeded = mock_ecb::seeded_db().await;
+        let routes = routes(
+            seeded.db.clone(),
+            StatusHandle::default(),
+            Some(MAX_BUSINESS_DAYS_BEHIND),
+        );
+
+        // seed rates are from 2019, long gone stale
+        let response = warp::test::request().path("/readyz").reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let response = warp::test::request().path("/healthz").reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
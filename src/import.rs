@@ -0,0 +1,265 @@
+// reference rates from a local file, to bootstrap or update the database without reaching the ECB.
+// Files are eurofxref xml documents like eurofxref-hist.xml or seed_rates.xml, the ECB's csv of
+// the same rates, or a zip with either of them like eurofxref-hist.zip
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use rust_decimal::Decimal;
+
+use crate::error::Error;
+use crate::fetcher::{
+    self, Currency, Date, DateParser, DateStream, Fetched, RateSource, Validators,
+};
+
+type Dates<'a> = Box<dyn Iterator<Item = Result<Date, Error>> + 'a>;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Format {
+    Xml,
+    Csv,
+    Zip,
+}
+
+impl Format {
+    fn of(path: &Path) -> Format {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("zip") => Format::Zip,
+            Some("csv") => Format::Csv,
+            _ => Format::Xml,
+        }
+    }
+}
+
+// a local file of rates, newest first like the ECB publishes them
+#[derive(Clone, Debug)]
+pub struct LocalFile {
+    path: PathBuf,
+}
+
+impl LocalFile {
+    pub fn new<P: Into<PathBuf>>(path: P) -> LocalFile {
+        LocalFile { path: path.into() }
+    }
+
+    fn dates(&self) -> Result<DateStream, Error> {
+        let file = File::open(&self.path).map_err(|err| {
+            Error::Import(format!("could not open {}, {}", self.path.display(), err))
+        })?;
+
+        let dates = match Format::of(&self.path) {
+            Format::Xml => fetcher::parse(BufReader::new(file)),
+            Format::Csv => fetcher::stream_blocking(move || csv_dates(BufReader::new(file))),
+            Format::Zip => {
+                let path = self.path.clone();
+                fetcher::send_blocking(move |send| {
+                    if let Err(err) = unzip(file, send) {
+                        send(Err(Error::Import(format!(
+                            "could not unzip {}, {}",
+                            path.display(),
+                            err
+                        ))));
+                    }
+                })
+            }
+        };
+        Ok(dates)
+    }
+}
+
+#[async_trait]
+impl RateSource for LocalFile {
+    fn url(&self, _document: &str) -> String {
+        self.path.display().to_string()
+    }
+
+    // the newest date of the file
+    async fn daily(&self, _validators: &Validators) -> Result<Fetched, Error> {
+        let dates = self.dates()?;
+        Ok(Fetched::Modified {
+            dates: Box::pin(futures::StreamExt::take(dates, 1)),
+            validators: Validators::default(),
+        })
+    }
+
+    async fn last90(&self) -> Result<DateStream, Error> {
+        self.dates()
+    }
+
+    async fn history(&self) -> Result<DateStream, Error> {
+        self.dates()
+    }
+}
+
+// send the dates of the first xml or csv file of the zip, parsed as the entry is decompressed
+fn unzip(file: File, send: &mut dyn FnMut(Result<Date, Error>) -> bool) -> Result<(), String> {
+    let mut archive = zip::ZipArchive::new(file).map_err(|err| err.to_string())?;
+    for index in 0..archive.len() {
+        let entry = archive.by_index(index).map_err(|err| err.to_string())?;
+        let name = entry.name().to_ascii_lowercase();
+        let dates: Dates = match Format::of(Path::new(&name)) {
+            Format::Zip => continue,
+            Format::Csv => csv_dates(entry),
+            Format::Xml if name.ends_with(".xml") => {
+                Box::new(DateParser::new(BufReader::new(entry)))
+            }
+            Format::Xml => continue,
+        };
+        for date in dates {
+            if !send(date) {
+                break;
+            }
+        }
+        return Ok(());
+    }
+    Err("no xml or csv file found in it".into())
+}
+
+// dates of the ECB's csv of reference rates, a `Date` column followed by a column per currency,
+// where `N/A` marks the days a currency has no rate
+struct CsvDates<R: Read> {
+    records: csv::StringRecordsIntoIter<R>,
+    currencies: Vec<String>,
+}
+
+fn csv_dates<'a, R: Read + 'a>(reader: R) -> Dates<'a> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .from_reader(reader);
+    let currencies = match reader.headers() {
+        Ok(headers) => headers.iter().skip(1).map(String::from).collect(),
+        Err(err) => return Box::new(std::iter::once(Err(csv_error(err)))),
+    };
+    Box::new(CsvDates {
+        records: reader.into_records(),
+        currencies,
+    })
+}
+
+impl<R: Read> Iterator for CsvDates<R> {
+    type Item = Result<Date, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = match self.records.next()? {
+            Ok(record) => record,
+            Err(err) => return Some(Err(csv_error(err))),
+        };
+        let value = record.get(0).unwrap_or_default().to_string();
+        let mut currencies = Vec::new();
+        for (name, rate) in self.currencies.iter().zip(record.iter().skip(1)) {
+            if name.is_empty() || rate.is_empty() || rate == "N/A" {
+                continue;
+            }
+            match Decimal::from_str(rate) {
+                Ok(rate) => currencies.push(Currency {
+                    name: name.clone(),
+                    rate,
+                }),
+                Err(err) => {
+                    let msg = format!(
+                        "could not parse {} rate {} on {}, {}",
+                        name, rate, value, err
+                    );
+                    return Some(Err(Error::Import(msg)));
+                }
+            }
+        }
+        Some(Ok(Date { value, currencies }))
+    }
+}
+
+fn csv_error(err: csv::Error) -> Error {
+    Error::Import(format!("could not read csv, {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_ecb;
+    use futures::TryStreamExt;
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    const CSV: &str = "Date, USD, JPY, HRK, \n\
+                       2023-01-03, 1.0545, 138.84, N/A, \n\
+                       2022-12-30, 1.0666, 140.66, 7.5365, \n";
+
+    async fn history(source: &LocalFile) -> Result<Vec<Date>, Error> {
+        source.history().await?.try_collect().await
+    }
+
+    #[tokio::test]
+    async fn xml() {
+        let source = LocalFile::new("seed_rates.xml");
+        assert_eq!(history(&source).await.unwrap(), mock_ecb::seed_dates());
+
+        let dates = match source.daily(&Validators::default()).await.unwrap() {
+            Fetched::Modified { dates, .. } => dates.try_collect::<Vec<Date>>().await.unwrap(),
+            Fetched::NotModified => panic!("local files are always modified"),
+        };
+        assert_eq!(dates, mock_ecb::seed_dates()[..1]);
+
+        assert!(matches!(
+            LocalFile::new("missing.xml").history().await,
+            Err(Error::Import(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn csv() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("eurofxref-hist.csv");
+        std::fs::write(&path, CSV).unwrap();
+
+        let dates = history(&LocalFile::new(&path)).await.unwrap();
+        assert_eq!(dates.len(), 2);
+        assert_eq!(dates[0].value, "2023-01-03");
+        let names = dates[0]
+            .currencies
+            .iter()
+            .map(|currency| currency.name.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(names, ["USD", "JPY"]);
+        assert_eq!(dates[1].currencies[2].rate, Decimal::new(75365, 4));
+
+        std::fs::write(&path, "Date,USD\n2023-01-03,1.05x\n").unwrap();
+        assert!(history(&LocalFile::new(&path)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn zip() {
+        let dir = tempdir().unwrap();
+        let zipped = |name: &str, file: &str, content: &str| {
+            let path = dir.path().join(name);
+            let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+            zip.start_file(file, zip::write::FileOptions::default())
+                .unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+            zip.finish().unwrap();
+            LocalFile::new(path)
+        };
+
+        let source = zipped("eurofxref-hist.zip", "eurofxref-hist.csv", CSV);
+        assert_eq!(history(&source).await.unwrap().len(), 2);
+
+        let xml = mock_ecb::envelope(&mock_ecb::seed_dates());
+        let source = zipped("seed.zip", "seed_rates.xml", &xml);
+        assert_eq!(history(&source).await.unwrap(), mock_ecb::seed_dates());
+        // the entry is read only until the stream is dropped
+        let dates = match source.daily(&Validators::default()).await.unwrap() {
+            Fetched::Modified { dates, .. } => dates.try_collect::<Vec<Date>>().await.unwrap(),
+            Fetched::NotModified => panic!("local files are always modified"),
+        };
+        assert_eq!(dates, mock_ecb::seed_dates()[..1]);
+
+        let source = zipped("empty.zip", "README", "");
+        assert!(matches!(history(&source).await, Err(Error::Import(_))));
+    }
+}
@@ -0,0 +1,108 @@
+// ISO 4217 metadata of the currencies the ECB publishes or has published reference rates for,
+// and of the legacy currencies replaced by the euro
+#[derive(Debug, PartialEq)]
+pub struct Metadata {
+    pub code: &'static str,
+    pub name: &'static str,
+    // kept as a string to preserve the leading zeros
+    pub numeric: &'static str,
+    pub minor_units: u8,
+    pub symbol: &'static str,
+}
+
+const fn metadata(
+    code: &'static str,
+    name: &'static str,
+    numeric: &'static str,
+    minor_units: u8,
+    symbol: &'static str,
+) -> Metadata {
+    Metadata {
+        code,
+        name,
+        numeric,
+        minor_units,
+        symbol,
+    }
+}
+
+// sorted by code
+static CURRENCIES: &[Metadata] = &[
+    metadata("ATS", "Schilling", "040", 2, "S"),
+    metadata("AUD", "Australian Dollar", "036", 2, "A$"),
+    metadata("BEF", "Belgian Franc", "056", 0, "fr"),
+    metadata("BGN", "Bulgarian Lev", "975", 2, "лв"),
+    metadata("BRL", "Brazilian Real", "986", 2, "R$"),
+    metadata("CAD", "Canadian Dollar", "124", 2, "C$"),
+    metadata("CHF", "Swiss Franc", "756", 2, "CHF"),
+    metadata("CNY", "Yuan Renminbi", "156", 2, "¥"),
+    metadata("CYP", "Cyprus Pound", "196", 2, "£C"),
+    metadata("CZK", "Czech Koruna", "203", 2, "Kč"),
+    metadata("DEM", "Deutsche Mark", "276", 2, "DM"),
+    metadata("DKK", "Danish Krone", "208", 2, "kr"),
+    metadata("EEK", "Kroon", "233", 2, "kr"),
+    metadata("ESP", "Spanish Peseta", "724", 0, "Pta"),
+    metadata("EUR", "Euro", "978", 2, "€"),
+    metadata("FIM", "Markka", "246", 2, "mk"),
+    metadata("FRF", "French Franc", "250", 2, "F"),
+    metadata("GBP", "Pound Sterling", "826", 2, "£"),
+    metadata("GRD", "Drachma", "300", 0, "Dr"),
+    metadata("HKD", "Hong Kong Dollar", "344", 2, "HK$"),
+    metadata("HRK", "Kuna", "191", 2, "kn"),
+    metadata("HUF", "Forint", "348", 2, "Ft"),
+    metadata("IDR", "Rupiah", "360", 2, "Rp"),
+    metadata("IEP", "Irish Pound", "372", 2, "£"),
+    metadata("ILS", "New Israeli Sheqel", "376", 2, "₪"),
+    metadata("INR", "Indian Rupee", "356", 2, "₹"),
+    metadata("ISK", "Iceland Krona", "352", 0, "kr"),
+    metadata("ITL", "Italian Lira", "380", 0, "₤"),
+    metadata("JPY", "Yen", "392", 0, "¥"),
+    metadata("KRW", "Won", "410", 0, "₩"),
+    metadata("LTL", "Lithuanian Litas", "440", 2, "Lt"),
+    metadata("LUF", "Luxembourg Franc", "442", 0, "F"),
+    metadata("LVL", "Latvian Lats", "428", 2, "Ls"),
+    metadata("MTL", "Maltese Lira", "470", 2, "Lm"),
+    metadata("MXN", "Mexican Peso", "484", 2, "Mex$"),
+    metadata("MYR", "Malaysian Ringgit", "458", 2, "RM"),
+    metadata("NLG", "Netherlands Guilder", "528", 2, "ƒ"),
+    metadata("NOK", "Norwegian Krone", "578", 2, "kr"),
+    metadata("NZD", "New Zealand Dollar", "554", 2, "NZ$"),
+    metadata("PHP", "Philippine Peso", "608", 2, "₱"),
+    metadata("PLN", "Zloty", "985", 2, "zł"),
+    metadata("PTE", "Portuguese Escudo", "620", 0, "Esc"),
+    metadata("ROL", "Romanian Leu", "642", 2, "L"),
+    metadata("RON", "Romanian Leu", "946", 2, "lei"),
+    metadata("RUB", "Russian Ruble", "643", 2, "₽"),
+    metadata("SEK", "Swedish Krona", "752", 2, "kr"),
+    metadata("SGD", "Singapore Dollar", "702", 2, "S$"),
+    metadata("SIT", "Tolar", "705", 2, "SIT"),
+    metadata("SKK", "Slovak Koruna", "703", 2, "Sk"),
+    metadata("THB", "Baht", "764", 2, "฿"),
+    metadata("TRL", "Turkish Lira", "792", 0, "TL"),
+    metadata("TRY", "Turkish Lira", "949", 2, "₺"),
+    metadata("USD", "US Dollar", "840", 2, "$"),
+    metadata("ZAR", "Rand", "710", 2, "R"),
+];
+
+pub fn lookup(code: &str) -> Option<&'static Metadata> {
+    CURRENCIES
+        .binary_search_by(|currency| currency.code.cmp(code))
+        .ok()
+        .map(|index| &CURRENCIES[index])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookup() {
+        assert!(CURRENCIES
+            .windows(2)
+            .all(|pair| pair[0].code < pair[1].code));
+        assert_eq!(super::lookup("AUD").unwrap().numeric, "036");
+        assert_eq!(super::lookup("JPY").unwrap().minor_units, 0);
+        assert_eq!(super::lookup("TRL").unwrap().name, "Turkish Lira");
+        assert_eq!(super::lookup("XXX"), None);
+    }
+}
@@ -0,0 +1,125 @@
+use rust_decimal::Decimal;
+
+use crate::fetcher::{Currency, Date};
+
+// currency replaced by the euro, worth `rate` per euro from the `since` date onward
+#[derive(Debug, PartialEq)]
+pub struct Legacy {
+    pub code: &'static str,
+    pub rate: Decimal,
+    pub since: &'static str,
+}
+
+const fn legacy(code: &'static str, mantissa: u32, scale: u32, since: &'static str) -> Legacy {
+    Legacy {
+        code,
+        rate: Decimal::from_parts(mantissa, 0, 0, false, scale),
+        since,
+    }
+}
+
+// irrevocably fixed euro conversion rates, sorted by code
+pub static CURRENCIES: &[Legacy] = &[
+    legacy("ATS", 137603, 4, "1999-01-01"),
+    legacy("BEF", 403399, 4, "1999-01-01"),
+    legacy("BGN", 195583, 5, "2026-01-01"),
+    legacy("CYP", 585274, 6, "2008-01-01"),
+    legacy("DEM", 195583, 5, "1999-01-01"),
+    legacy("EEK", 156466, 4, "2011-01-01"),
+    legacy("ESP", 166386, 3, "1999-01-01"),
+    legacy("FIM", 594573, 5, "1999-01-01"),
+    legacy("FRF", 655957, 5, "1999-01-01"),
+    legacy("GRD", 340750, 3, "2001-01-01"),
+    legacy("HRK", 753450, 5, "2023-01-01"),
+    legacy("IEP", 787564, 6, "1999-01-01"),
+    legacy("ITL", 193627, 2, "1999-01-01"),
+    legacy("LTL", 345280, 5, "2015-01-01"),
+    legacy("LUF", 403399, 4, "1999-01-01"),
+    legacy("LVL", 702804, 6, "2014-01-01"),
+    legacy("MTL", 429300, 6, "2008-01-01"),
+    legacy("NLG", 220371, 5, "1999-01-01"),
+    legacy("PTE", 200482, 3, "1999-01-01"),
+    legacy("SIT", 239640, 3, "2007-01-01"),
+    legacy("SKK", 301260, 4, "2009-01-01"),
+];
+
+pub fn lookup(code: &str) -> Option<&'static Legacy> {
+    CURRENCIES
+        .binary_search_by(|currency| currency.code.cmp(code))
+        .ok()
+        .map(|index| &CURRENCIES[index])
+}
+
+// add the fixed rates of the legacy currencies in `codes` to the date, when it is on or after
+// their adoption of the euro and they have no published rate on it
+pub fn derive(mut date: Date, codes: &[&str]) -> Date {
+    for code in codes {
+        let legacy = match lookup(code) {
+            Some(legacy) => legacy,
+            None => continue,
+        };
+        if date.value.as_str() >= legacy.since && date.currencies.iter().all(|c| c.name != *code) {
+            date.currencies.push(Currency {
+                name: legacy.code.to_string(),
+                rate: legacy.rate,
+            });
+        }
+    }
+    date
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn lookup() {
+        assert!(CURRENCIES
+            .windows(2)
+            .all(|pair| pair[0].code < pair[1].code));
+        assert_eq!(
+            super::lookup("DEM").unwrap().rate,
+            Decimal::from_str("1.95583").unwrap()
+        );
+        assert_eq!(
+            super::lookup("ITL").unwrap().rate,
+            Decimal::from_str("1936.27").unwrap()
+        );
+        assert_eq!(super::lookup("USD"), None);
+    }
+
+    #[test]
+    fn derive() {
+        let date = |value: &str, currencies: &[(&str, &str)]| Date {
+            value: value.to_string(),
+            currencies: currencies
+                .iter()
+                .map(|(name, rate)| Currency {
+                    name: name.to_string(),
+                    rate: Decimal::from_str(rate).unwrap(),
+                })
+                .collect(),
+        };
+
+        let derived = super::derive(
+            date("2022-12-30", &[("HRK", "7.5365")]),
+            &["DEM", "HRK", "USD"],
+        );
+        // published rates are kept
+        assert_eq!(
+            derived,
+            date("2022-12-30", &[("HRK", "7.5365"), ("DEM", "1.95583")])
+        );
+
+        let derived = super::derive(date("2023-01-02", &[]), &["HRK", "SIT"]);
+        assert_eq!(
+            derived,
+            date("2023-01-02", &[("HRK", "7.5345"), ("SIT", "239.640")])
+        );
+
+        // before adopting the euro
+        let derived = super::derive(date("2000-12-29", &[]), &["GRD"]);
+        assert!(derived.currencies.is_empty());
+    }
+}
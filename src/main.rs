@@ -1,47 +1,115 @@
+mod aggregate;
 mod api;
+mod caching;
+mod compression;
+mod config;
+mod cors;
 mod db;
 mod error;
 mod fetcher;
 mod handlers;
+mod health;
+mod import;
+mod iso4217;
+mod legacy;
+mod metrics;
+#[cfg(test)]
+mod mock_ecb;
+mod stats;
+mod updater;
 
-use std::env;
+use std::path::Path;
 use std::sync::Arc;
 use std::time::Duration;
 
+use crate::config::{Args, Config};
 use crate::error::Error;
+use crate::import::LocalFile;
+use clap::Parser;
 use exitfailure::ExitDisplay;
-use futures::StreamExt;
-use tokio_stream::wrappers::IntervalStream;
 use warp::Filter;
 
 #[tokio::main]
 async fn main() -> Result<(), ExitDisplay<Error>> {
-    env_logger::init();
-    let port = env::var("PORT").unwrap_or_else(|_| "3030".to_string());
-    let port = port.parse().map_err(|err| Error::InvalidPort(port, err))?;
+    let args = Args::parse();
+    let config = Config::load(&args)?;
+    if args.print_config {
+        print!("{}", config.to_toml()?);
+        return Ok(());
+    }
 
-    let db_location = std::env::var("DB_LOCATION").unwrap_or_else(|_| "db".to_string());
-    let db = db::init(&db_location).await?;
+    env_logger::Builder::new().parse_filters(&config.log).init();
+    let cors = config.cors.filter()?;
+    let db_exists = Path::new(&config.db_location).exists();
+
+    // a new database is created from the imported file itself
+    if let Some(ref file) = args.import {
+        let file = LocalFile::new(file);
+        let db = db::init(&config.db_location, &file).await?;
+        if db_exists {
+            db::import(&db, &file).await?;
+        }
+        return Ok(());
+    }
+
+    let source = fetcher::Ecb::new(config.source.ecb_url);
+    let db = match config.source.bootstrap_file {
+        Some(ref file) => db::init(&config.db_location, &LocalFile::new(file)).await?,
+        None if !db_exists && !config.update.enabled => {
+            return Err(Error::Config(
+                "no-fetch mode needs an existing database or a bootstrap file".into(),
+            )
+            .into());
+        }
+        None => db::init(&config.db_location, &source).await?,
+    };
     let db_filter = Arc::new(db.clone());
 
     // launch updater daemon
-    tokio::spawn(async move {
-        let interval = tokio::time::interval(Duration::from_secs(360));
-        let mut interval_stream = IntervalStream::new(interval);
-        while interval_stream.next().await.is_some() {
-            db::update(&db).await.expect("error updating database!");
-        }
-    });
+    let metrics = metrics::Metrics::new();
+    let updater_status = updater::StatusHandle::default();
+    if config.update.enabled {
+        let updater = updater::Updater::new(
+            db,
+            Arc::new(source),
+            Duration::from_secs(config.update.interval),
+            updater_status.clone(),
+            metrics.clone(),
+        );
+        tokio::spawn(updater.run());
+    } else {
+        log::info!("no-fetch mode, rates only change through imports");
+    }
 
-    let api = api::routes(db_filter.clone());
+    // rates can't go stale without an updater
+    let max_business_days_behind = if config.update.enabled {
+        Some(health::MAX_BUSINESS_DAYS_BEHIND)
+    } else {
+        None
+    };
+    let api = api::routes(db_filter.clone(), cors, config.caching, metrics.clone());
+    let health = health::routes(db_filter.clone(), updater_status, max_business_days_behind);
+    let metrics_routes = metrics::routes(metrics.clone(), db_filter.clone());
 
     let ui = warp::path::end()
         .and(warp::get())
         .map(move || db_filter.clone())
         .and_then(handlers::index);
 
-    let routes = api.or(ui).recover(error::recover);
+    let routes = compression::wrap(api.or(ui), config.compression)
+        .or(health)
+        .or(metrics_routes)
+        .recover({
+            let metrics = metrics.clone();
+            move |err: warp::Rejection| {
+                metrics.observe_rejection(&err);
+                error::recover(err)
+            }
+        })
+        .with(warp::log::custom(move |info| metrics.observe_request(info)));
 
-    warp::serve(routes).run(([0, 0, 0, 0], port)).await;
+    warp::serve(routes)
+        .run((config.bind_address, config.port))
+        .await;
     Ok(())
 }
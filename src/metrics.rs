@@ -0,0 +1,231 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::NaiveDate;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+use warp::{Filter, Rejection, Reply};
+
+use crate::db::Db;
+use crate::error::Error;
+
+// prometheus metrics of the service, cheap to clone as all metrics are reference counted
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    request_duration: HistogramVec,
+    rejections: IntCounterVec,
+    updater_runs: IntCounterVec,
+    updater_duration: HistogramVec,
+    newest_date: IntGauge,
+    stored_days: IntGauge,
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        let registry = Registry::new_custom(Some("currencies".into()), None)
+            .expect("invalid metrics registry");
+
+        let requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests by route and status"),
+            &["route", "status"],
+        )
+        .expect("invalid http_requests_total metric");
+        let request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "HTTP request latency by route",
+            ),
+            &["route"],
+        )
+        .expect("invalid http_request_duration_seconds metric");
+        let rejections = IntCounterVec::new(
+            Opts::new("api_rejections_total", "API rejections by error"),
+            &["error"],
+        )
+        .expect("invalid api_rejections_total metric");
+        let updater_runs = IntCounterVec::new(
+            Opts::new("updater_runs_total", "Database updates by result"),
+            &["result"],
+        )
+        .expect("invalid updater_runs_total metric");
+        let updater_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "updater_run_duration_seconds",
+                "Database update duration by result",
+            )
+            .buckets(vec![0.1, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0]),
+            &["result"],
+        )
+        .expect("invalid updater_run_duration_seconds metric");
+        let newest_date = IntGauge::new(
+            "newest_rate_date_timestamp_seconds",
+            "Unix timestamp of the newest stored rates date",
+        )
+        .expect("invalid newest_rate_date_timestamp_seconds metric");
+        let stored_days = IntGauge::new("stored_days", "Number of days with stored rates")
+            .expect("invalid stored_days metric");
+
+        for collector in [
+            Box::new(requests.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(request_duration.clone()),
+            Box::new(rejections.clone()),
+            Box::new(updater_runs.clone()),
+            Box::new(updater_duration.clone()),
+            Box::new(newest_date.clone()),
+            Box::new(stored_days.clone()),
+        ] {
+            registry
+                .register(collector)
+                .expect("could not register metric");
+        }
+
+        Metrics {
+            registry,
+            requests,
+            request_duration,
+            rejections,
+            updater_runs,
+            updater_duration,
+            newest_date,
+            stored_days,
+        }
+    }
+
+    pub fn observe_request(&self, info: warp::log::Info<'_>) {
+        let route = route_label(info.path());
+        self.requests
+            .with_label_values(&[route, info.status().as_str()])
+            .inc();
+        self.request_duration
+            .with_label_values(&[route])
+            .observe(info.elapsed().as_secs_f64());
+    }
+
+    pub fn observe_rejection(&self, rejection: &Rejection) {
+        if let Some(err) = rejection.find::<Error>() {
+            self.rejections.with_label_values(&[err.kind()]).inc();
+        }
+    }
+
+    pub fn observe_update(&self, success: bool, elapsed: Duration) {
+        let result = if success { "success" } else { "failure" };
+        self.updater_runs.with_label_values(&[result]).inc();
+        self.updater_duration
+            .with_label_values(&[result])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    async fn render(&self, db: &Db) -> Result<String, Error> {
+        let current = db.get_current_rates().await?.value_as_date()?;
+        self.newest_date.set(current.and_hms(0, 0, 0).timestamp());
+        self.stored_days.set(db.count_days().await? as i64);
+
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .map_err(|err| Error::Metrics(err.to_string()))?;
+        String::from_utf8(buffer).map_err(|err| Error::Metrics(err.to_string()))
+    }
+}
+
+// /metrics endpoint in prometheus text format
+pub fn routes(
+    metrics: Metrics,
+    db: Arc<Db>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path("metrics")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and_then(move || {
+            let metrics = metrics.clone();
+            let db = db.clone();
+            async move {
+                let body = metrics.render(&db).await?;
+                Ok::<_, Rejection>(warp::reply::with_header(
+                    body,
+                    "content-type",
+                    prometheus::TEXT_FORMAT,
+                ))
+            }
+        })
+}
+
+// group request paths by route, so that each date requested doesn't create a new label
+fn route_label(path: &str) -> &'static str {
+    let segments = path
+        .trim_matches('/')
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<&str>>();
+    match segments.as_slice() {
+        [] => "index",
+        ["healthz"] => "healthz",
+        ["readyz"] => "readyz",
+        ["metrics"] => "metrics",
+        ["api", "v1", "latest"] => "latest",
+        ["api", "v1", "history"] => "history",
+        ["api", "v1", "convert"] => "convert",
+        ["api", "v1", "convert", "batch"] => "convert_batch",
+        ["api", "v1", "fluctuation"] => "fluctuation",
+        ["api", "v1", "stats"] => "stats",
+        ["api", "v1", "currencies"] => "currencies",
+        ["api", "v1", "matrix"] => "matrix",
+        ["api", "v1", day] if day.parse::<NaiveDate>().is_ok() => "day",
+        _ => "other",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_ecb;
+
+    #[test]
+    fn route_label() {
+        assert_eq!(super::route_label("/"), "index");
+        assert_eq!(super::route_label("/api/v1/latest"), "latest");
+        assert_eq!(super::route_label("/api/v1/history"), "history");
+        assert_eq!(super::route_label("/api/v1/fluctuation"), "fluctuation");
+        assert_eq!(super::route_label("/api/v1/2019-10-18"), "day");
+        assert_eq!(super::route_label("/api/v1/2019-13-18"), "other");
+        assert_eq!(super::route_label("/metrics"), "metrics");
+        assert_eq!(super::route_label("/wp-admin"), "other");
+    }
+
+    #[test]
+    fn observe_update() {
+        let metrics = Metrics::new();
+        metrics.observe_update(true, Duration::from_millis(20));
+        metrics.observe_update(false, Duration::from_millis(20));
+        metrics.observe_update(false, Duration::from_millis(20));
+        assert_eq!(
+            metrics.updater_runs.with_label_values(&["success"]).get(),
+            1
+        );
+        assert_eq!(
+            metrics.updater_runs.with_label_values(&["failure"]).get(),
+            2
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn metrics_endpoint() {
+        let seeded = mock_ecb::seeded_db().await;
+        let metrics = Metrics::new();
+        metrics.observe_rejection(&Error::InvalidSymbol.into());
+        let routes = routes(metrics, seeded.db.clone());
+
+        let response = warp::test::request().path("/metrics").reply(&routes).await;
+        let body = String::from_utf8(response.body().to_vec()).unwrap();
+        assert!(body.contains(&format!(
+            "currencies_stored_days {}",
+            mock_ecb::seed_dates().len()
+        )));
+        // 2019-10-18
+        assert!(body.contains("currencies_newest_rate_date_timestamp_seconds 1571356800"));
+        assert!(body.contains(r#"currencies_api_rejections_total{error="invalid_symbol"} 1"#));
+    }
+}
@@ -0,0 +1,186 @@
+// in-process stand-in for the ECB's eurofxref endpoints, used on tests so that they don't depend
+// on reaching the real ECB
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use chrono::Duration;
+use tempfile::TempDir;
+use warp::http::{header, Response, StatusCode};
+use warp::Filter;
+
+use crate::db::{self, Db};
+use crate::fetcher::{Date, Ecb, Envelope, ECB_DAILY, ECB_HIST, ECB_HIST_LAST_90};
+
+#[derive(Default)]
+struct State {
+    // newest first, as served by ECB
+    dates: Vec<Date>,
+    hits: HashMap<&'static str, usize>,
+    not_modified: HashMap<&'static str, usize>,
+}
+
+pub struct MockEcb {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+}
+
+impl MockEcb {
+    // start serving `dates` on a random local port
+    pub async fn start(dates: Vec<Date>) -> MockEcb {
+        let state = Arc::new(Mutex::new(State {
+            dates,
+            ..Default::default()
+        }));
+
+        let routes = endpoint(ECB_DAILY, state.clone())
+            .or(endpoint(ECB_HIST_LAST_90, state.clone()))
+            .or(endpoint(ECB_HIST, state.clone()));
+
+        let (addr, server) = warp::serve(routes).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        MockEcb { addr, state }
+    }
+
+    pub fn base_url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    // replace the served dates, e.g. to simulate new days being published
+    pub fn set_dates(&self, dates: Vec<Date>) {
+        self.state.lock().unwrap().dates = dates;
+    }
+
+    // number of requests received by the given endpoint
+    pub fn hits(&self, endpoint: &str) -> usize {
+        *self.state.lock().unwrap().hits.get(endpoint).unwrap_or(&0)
+    }
+
+    // number of requests answered with 304 Not Modified by the given endpoint
+    pub fn not_modified(&self, endpoint: &str) -> usize {
+        *self
+            .state
+            .lock()
+            .unwrap()
+            .not_modified
+            .get(endpoint)
+            .unwrap_or(&0)
+    }
+}
+
+// a database bootstrapped with the seed dates from a mock ECB, the mock and the directory of the
+// database are kept for as long as it is used
+pub struct Seeded {
+    pub db: Arc<Db>,
+    pub mock: MockEcb,
+    _dir: TempDir,
+}
+
+pub async fn seeded_db() -> Seeded {
+    let mock = MockEcb::start(seed_dates()).await;
+    let dir = tempfile::tempdir().unwrap();
+    let db = db::init(dir.path().join("db"), &Ecb::new(mock.base_url()))
+        .await
+        .unwrap();
+    Seeded {
+        db: Arc::new(db),
+        mock,
+        _dir: dir,
+    }
+}
+
+// documents are served with an `ETag` hashed from their content and a `Last-Modified` at the
+// ECB's publication time of their newest date, and honour conditional requests for them
+fn endpoint(
+    file: &'static str,
+    state: Arc<Mutex<State>>,
+) -> impl Filter<Extract = (Response<String>,), Error = warp::Rejection> + Clone {
+    warp::path(file)
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::header::optional::<String>("if-none-match"))
+        .and(warp::header::optional::<String>("if-modified-since"))
+        .map(
+            move |if_none_match: Option<String>, if_modified_since: Option<String>| {
+                let mut state = state.lock().unwrap();
+                *state.hits.entry(file).or_insert(0) += 1;
+                let dates = match file {
+                    ECB_DAILY => state.dates.iter().take(1).cloned().collect(),
+                    ECB_HIST_LAST_90 => last90(&state.dates),
+                    _ => state.dates.clone(),
+                };
+                let body = envelope(&dates);
+
+                let mut hasher = DefaultHasher::new();
+                body.hash(&mut hasher);
+                let etag = format!("\"{:x}\"", hasher.finish());
+                let last_modified = dates
+                    .first()
+                    .map(|date| {
+                        date.value_as_date()
+                            .unwrap()
+                            .format("%a, %d %b %Y 15:00:00 GMT")
+                            .to_string()
+                    })
+                    .unwrap_or_default();
+
+                let unchanged = match if_none_match {
+                    Some(if_none_match) => if_none_match == etag,
+                    None => if_modified_since.is_some_and(|since| since == last_modified),
+                };
+                let res = Response::builder()
+                    .header(header::ETAG, etag)
+                    .header(header::LAST_MODIFIED, last_modified);
+                if unchanged {
+                    *state.not_modified.entry(file).or_insert(0) += 1;
+                    return res
+                        .status(StatusCode::NOT_MODIFIED)
+                        .body(String::new())
+                        .unwrap();
+                }
+                res.body(body).unwrap()
+            },
+        )
+}
+
+fn last90(dates: &[Date]) -> Vec<Date> {
+    let newest = match dates.first() {
+        Some(newest) => newest.value_as_date().unwrap(),
+        None => return Vec::new(),
+    };
+    dates
+        .iter()
+        .filter(|date| newest - date.value_as_date().unwrap() < Duration::days(90))
+        .cloned()
+        .collect()
+}
+
+// render dates in the ECB's eurofxref xml format
+pub fn envelope(dates: &[Date]) -> String {
+    let mut xml = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8"?><gesmes:Envelope xmlns:gesmes="http://www.gesmes.org/xml/2002-08-01" xmlns="http://www.ecb.int/vocabulary/2002-08-01/eurofxref"><gesmes:subject>Reference rates</gesmes:subject><gesmes:Sender><gesmes:name>European Central Bank</gesmes:name></gesmes:Sender><Cube>"#,
+    );
+    for date in dates {
+        xml.push_str(&format!(r#"<Cube time="{}">"#, date.value));
+        for currency in &date.currencies {
+            xml.push_str(&format!(
+                r#"<Cube currency="{}" rate="{}"/>"#,
+                currency.name, currency.rate
+            ));
+        }
+        xml.push_str("</Cube>");
+    }
+    xml.push_str("</Cube></gesmes:Envelope>");
+    xml
+}
+
+// rates from seed_rates.xml, from 2019-10-18 back to 2019-07-22
+pub fn seed_dates() -> Vec<Date> {
+    let file = File::open("seed_rates.xml").unwrap();
+    let envelope: Envelope = serde_xml_rs::from_reader(&file).unwrap();
+    envelope.cube.dates
+}
@@ -0,0 +1,175 @@
+use std::collections::BTreeMap;
+
+use rust_decimal::{Decimal, MathematicalOps};
+
+use crate::error::Error;
+
+// descriptive statistics of a currency's rates over a date range
+#[derive(Debug, PartialEq)]
+pub struct Stats {
+    pub days: usize,
+    pub min: Decimal,
+    pub min_date: String,
+    pub max: Decimal,
+    pub max_date: String,
+    pub mean: Decimal,
+    pub median: Decimal,
+    // sample standard deviation, needs at least 2 rates
+    pub std_dev: Option<Decimal>,
+    // sample standard deviation of the daily log returns, needs at least 3 rates
+    pub volatility: Option<Decimal>,
+}
+
+// statistics of each currency over the rates of each date, ascending, currencies keep the order
+// they first appear in
+pub fn stats(
+    rates: &BTreeMap<String, Vec<(String, Decimal)>>,
+) -> Result<Vec<(String, Stats)>, Error> {
+    let mut series: Vec<(&str, Vec<(&str, Decimal)>)> = Vec::new();
+    for (date, currencies) in rates {
+        for (name, rate) in currencies {
+            match series.iter_mut().find(|(n, _)| n == name) {
+                Some((_, rates)) => rates.push((date, *rate)),
+                None => series.push((name, vec![(date, *rate)])),
+            }
+        }
+    }
+
+    series
+        .into_iter()
+        .map(|(name, rates)| Ok((name.to_string(), currency_stats(&rates)?)))
+        .collect()
+}
+
+// rates are in date order and never empty
+fn currency_stats(rates: &[(&str, Decimal)]) -> Result<Stats, Error> {
+    // the first date wins on ties
+    let (min_date, min) = rates.iter().fold(
+        rates[0],
+        |min, rate| if rate.1 < min.1 { *rate } else { min },
+    );
+    let (max_date, max) = rates.iter().fold(
+        rates[0],
+        |max, rate| if rate.1 > max.1 { *rate } else { max },
+    );
+
+    let values = rates
+        .iter()
+        .map(|(_, rate)| *rate)
+        .collect::<Vec<Decimal>>();
+    let average = mean(&values).ok_or(Error::Overflow("mean"))?;
+
+    let mut sorted = values.clone();
+    sorted.sort_unstable();
+    let middle = sorted.len() / 2;
+    let median = if sorted.len() % 2 == 0 {
+        mean(&sorted[middle - 1..=middle]).ok_or(Error::Overflow("median"))?
+    } else {
+        sorted[middle]
+    };
+
+    let deviation = match values.len() {
+        0 | 1 => None,
+        _ => Some(std_dev(&values).ok_or(Error::Overflow("std_dev"))?),
+    };
+
+    let returns = values
+        .windows(2)
+        .map(|pair| {
+            pair[1]
+                .checked_div(pair[0])
+                .and_then(|ratio| ratio.checked_ln())
+        })
+        .collect::<Option<Vec<Decimal>>>()
+        .ok_or(Error::Overflow("volatility"))?;
+    let volatility = match returns.len() {
+        0 | 1 => None,
+        _ => Some(std_dev(&returns).ok_or(Error::Overflow("volatility"))?),
+    };
+
+    Ok(Stats {
+        days: rates.len(),
+        min,
+        min_date: min_date.to_string(),
+        max,
+        max_date: max_date.to_string(),
+        mean: average,
+        median,
+        std_dev: deviation,
+        volatility,
+    })
+}
+
+fn mean(values: &[Decimal]) -> Option<Decimal> {
+    values
+        .iter()
+        .try_fold(Decimal::ZERO, |sum, value| sum.checked_add(*value))?
+        .checked_div(Decimal::from(values.len()))
+}
+
+// sample standard deviation, values must have at least 2 elements
+fn std_dev(values: &[Decimal]) -> Option<Decimal> {
+    let mean = mean(values)?;
+    let squares = values.iter().try_fold(Decimal::ZERO, |sum, value| {
+        let deviation = value.checked_sub(mean)?;
+        sum.checked_add(deviation.checked_mul(deviation)?)
+    })?;
+    squares.checked_div(Decimal::from(values.len() - 1))?.sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn stats() {
+        let rates = [
+            ("2019-08-01", "1.2"),
+            ("2019-08-02", "1.1"),
+            ("2019-08-05", "1.4"),
+            ("2019-08-06", "1.1"),
+        ]
+        .iter()
+        .map(|(day, rate)| {
+            (
+                day.to_string(),
+                vec![("USD".to_string(), Decimal::from_str(rate).unwrap())],
+            )
+        })
+        .collect();
+
+        let stats = super::stats(&rates).unwrap();
+        assert_eq!(stats.len(), 1);
+        let (name, stats) = &stats[0];
+        assert_eq!(name, "USD");
+        assert_eq!(stats.days, 4);
+        assert_eq!(stats.min, Decimal::from_str("1.1").unwrap());
+        assert_eq!(stats.min_date, "2019-08-02");
+        assert_eq!(stats.max, Decimal::from_str("1.4").unwrap());
+        assert_eq!(stats.max_date, "2019-08-05");
+        assert_eq!(stats.mean, Decimal::from_str("1.2").unwrap());
+        assert_eq!(stats.median, Decimal::from_str("1.15").unwrap());
+        // variance of 0.06 / 3
+        assert_eq!(
+            stats.std_dev.unwrap().round_dp(6),
+            Decimal::from_str("0.141421").unwrap()
+        );
+        assert!(stats.volatility.unwrap() > Decimal::ZERO);
+    }
+
+    #[test]
+    fn stats_single_day() {
+        let rates = std::iter::once((
+            "2019-08-01".to_string(),
+            vec![("USD".to_string(), Decimal::ONE)],
+        ))
+        .collect();
+
+        let stats = super::stats(&rates).unwrap();
+        let (_, stats) = &stats[0];
+        assert_eq!(stats.median, Decimal::ONE);
+        assert_eq!(stats.std_dev, None);
+        assert_eq!(stats.volatility, None);
+    }
+}
@@ -0,0 +1,184 @@
+use std::cmp;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use serde::Serialize;
+
+use crate::db::{self, Db};
+use crate::error::Error;
+use crate::fetcher::RateSource;
+use crate::metrics::Metrics;
+
+// first retry delay after a failed update, doubled on each consecutive failure
+const INITIAL_BACKOFF: Duration = Duration::from_secs(5);
+
+// state of the updater, shared with the rest of the app
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Status {
+    pub last_success: Option<DateTime<Utc>>,
+    pub last_failure: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub consecutive_failures: u32,
+}
+
+#[derive(Clone, Default)]
+pub struct StatusHandle {
+    inner: Arc<RwLock<Status>>,
+}
+
+impl StatusHandle {
+    pub fn get(&self) -> Status {
+        self.inner
+            .read()
+            .expect("updater status lock poisoned")
+            .clone()
+    }
+
+    fn success(&self) {
+        let mut status = self.inner.write().expect("updater status lock poisoned");
+        status.last_success = Some(Utc::now());
+        status.consecutive_failures = 0;
+    }
+
+    fn failure(&self, err: &Error) {
+        let mut status = self.inner.write().expect("updater status lock poisoned");
+        status.last_failure = Some(Utc::now());
+        status.last_error = Some(err.to_string());
+        status.consecutive_failures += 1;
+    }
+}
+
+// keeps the database up to date with the rate source, checking it every `interval` and retrying
+// failed updates with exponential backoff
+pub struct Updater {
+    db: Db,
+    source: Arc<dyn RateSource>,
+    interval: Duration,
+    status: StatusHandle,
+    metrics: Metrics,
+}
+
+impl Updater {
+    pub fn new(
+        db: Db,
+        source: Arc<dyn RateSource>,
+        interval: Duration,
+        status: StatusHandle,
+        metrics: Metrics,
+    ) -> Updater {
+        Updater {
+            db,
+            source,
+            interval,
+            status,
+            metrics,
+        }
+    }
+
+    pub async fn run(self) {
+        loop {
+            let delay = match self.run_once().await {
+                Ok(()) => self.interval,
+                Err(_) => backoff(self.status.get().consecutive_failures, self.interval),
+            };
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    // run a single update on its own task so that a panic doesn't take the updater down with it
+    async fn run_once(&self) -> Result<(), Error> {
+        let db = self.db.clone();
+        let source = self.source.clone();
+        let start = Instant::now();
+        let result = tokio::spawn(async move { db::update(&db, source.as_ref()).await })
+            .await
+            .unwrap_or_else(|err| Err(Error::Updater(err.to_string())));
+
+        self.metrics.observe_update(result.is_ok(), start.elapsed());
+        match result {
+            Ok(()) => self.status.success(),
+            Err(ref err) => {
+                self.status.failure(err);
+                let status = self.status.get();
+                let last_success = status
+                    .last_success
+                    .map(|time| time.to_rfc3339())
+                    .unwrap_or_else(|| "never".into());
+                // the source being unreachable is expected from time to time
+                let level = if matches!(err, Error::Fetcher(_)) {
+                    log::Level::Warn
+                } else {
+                    log::Level::Error
+                };
+                log::log!(
+                    level,
+                    "error updating database, {}, {} consecutive failures, last success {}",
+                    err,
+                    status.consecutive_failures,
+                    last_success
+                );
+            }
+        }
+        result
+    }
+}
+
+// exponential backoff capped at `max`, with half of the delay randomized to spread retries
+fn backoff(failures: u32, max: Duration) -> Duration {
+    let exp = INITIAL_BACKOFF
+        .checked_mul(2u32.saturating_pow(failures.saturating_sub(1)))
+        .unwrap_or(max);
+    let delay = cmp::min(exp, max);
+    let half = delay / 2;
+    half + half.mul_f64(rand::thread_rng().gen::<f64>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetcher::Ecb;
+    use crate::mock_ecb;
+
+    #[test]
+    fn backoff() {
+        let max = Duration::from_secs(360);
+        for failures in 1..40 {
+            let delay = super::backoff(failures, max);
+            let exp = cmp::min(INITIAL_BACKOFF * 2u32.pow(cmp::min(failures - 1, 10)), max);
+            assert!(delay >= exp / 2 && delay <= exp);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn run_once_records_status() {
+        let seeded = mock_ecb::seeded_db().await;
+
+        // nothing listens on the discard port
+        let status = StatusHandle::default();
+        let updater = Updater::new(
+            Db::clone(&seeded.db),
+            Arc::new(Ecb::new("http://127.0.0.1:9")),
+            Duration::from_secs(360),
+            status.clone(),
+            Metrics::new(),
+        );
+        updater.run_once().await.unwrap_err();
+        updater.run_once().await.unwrap_err();
+        let status = status.get();
+        assert_eq!(status.consecutive_failures, 2);
+        assert!(status.last_failure.is_some());
+        assert!(status.last_error.is_some());
+        assert!(status.last_success.is_none());
+
+        let updater = Updater {
+            source: Arc::new(Ecb::new(seeded.mock.base_url())),
+            ..updater
+        };
+        updater.run_once().await.unwrap();
+        let status = updater.status.get();
+        assert_eq!(status.consecutive_failures, 0);
+        assert!(status.last_success.is_some());
+    }
+}